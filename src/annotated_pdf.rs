@@ -0,0 +1,357 @@
+use druid::im::Vector;
+use druid::{FileSpec, Rect, Selector};
+
+use mupdf::pdf::{PdfDocument, PdfObject};
+use mupdf::{Buffer, Error};
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::export::page_label;
+use crate::pdf_annotations::PageSpace;
+use crate::tag_table::TagDef;
+use crate::{DocumentInfo, PageNum};
+
+// true to also set each page's CropBox from its crop margins
+pub const EXPORT_ANNOTATED_PDF: Selector<bool> = Selector::new("export-annotated-pdf");
+
+pub const PDF_FILE: FileSpec = FileSpec::new("PDF file", &["pdf"]);
+
+struct OutlineEntry {
+    title: String,
+    page: Option<PageNum>,
+    children: Vec<OutlineEntry>,
+}
+
+fn numbers(pdf: &mut PdfDocument, values: &[f64]) -> Result<PdfObject, Error> {
+    let mut array = pdf.new_array()?;
+    for v in values {
+        array.array_push(pdf.new_real(*v as f32)?)?;
+    }
+    Ok(array)
+}
+
+fn rgb(pdf: &mut PdfDocument, tag: &TagDef) -> Result<PdfObject, Error> {
+    let (r, g, b, _) = tag.color().as_rgba8();
+    numbers(pdf, &[r as f64 / 255., g as f64 / 255., b as f64 / 255.])
+}
+
+// bookmarks by letter, then tagged pages grouped by tag
+fn outline_entries(info: &DocumentInfo, tags: &Vector<TagDef>) -> Vec<OutlineEntry> {
+    let mut entries = Vec::<OutlineEntry>::new();
+
+    let mut bookmarks: Vec<(&String, &PageNum)> = info.bookmarks.iter().collect();
+    bookmarks.sort();
+    if !bookmarks.is_empty() {
+        entries.push(OutlineEntry {
+            title: "Bookmarks".to_string(),
+            page: None,
+            children: bookmarks
+                .iter()
+                .map(|(letter, page)| OutlineEntry {
+                    title: format!("{}: {}", letter, page_label(info, **page)),
+                    page: Some(**page),
+                    children: Vec::new(),
+                })
+                .collect(),
+        });
+    }
+
+    let mut tagged_pages: Vec<(&PageNum, &u16)> =
+        info.tags.iter().filter(|(_, bits)| **bits != 0).collect();
+    tagged_pages.sort();
+    let tag_entries: Vec<OutlineEntry> = tags
+        .iter()
+        .enumerate()
+        .filter_map(|(tag, def)| {
+            let pages: Vec<OutlineEntry> = tagged_pages
+                .iter()
+                .filter(|(_, bits)| **bits & (1 << tag) != 0)
+                .map(|(page, _)| OutlineEntry {
+                    title: page_label(info, **page),
+                    page: Some(**page),
+                    children: Vec::new(),
+                })
+                .collect();
+            if pages.is_empty() {
+                None
+            } else {
+                Some(OutlineEntry {
+                    title: format!("{} (tag {})", def.name, tag),
+                    page: None,
+                    children: pages,
+                })
+            }
+        })
+        .collect();
+    if !tag_entries.is_empty() {
+        entries.push(OutlineEntry {
+            title: "Tagged pages".to_string(),
+            page: None,
+            children: tag_entries,
+        });
+    }
+
+    entries
+}
+
+fn link_siblings(items: &mut [PdfObject]) -> Result<(), Error> {
+    for i in 1..items.len() {
+        let prev = items[i - 1].clone();
+        let next = items[i].clone();
+        items[i].dict_put("Prev", prev)?;
+        items[i - 1].dict_put("Next", next)?;
+    }
+    Ok(())
+}
+
+// returns the new items, linked to each other but not yet to anything before them under `parent`
+fn write_outline_entries(
+    pdf: &mut PdfDocument,
+    parent: &PdfObject,
+    entries: &[OutlineEntry],
+) -> Result<Vec<PdfObject>, Error> {
+    let mut items = Vec::<PdfObject>::new();
+    for entry in entries {
+        let dict = pdf.new_dict()?;
+        let mut item = pdf.add_object(&dict)?;
+        item.dict_put("Title", pdf.new_string(&entry.title)?)?;
+        item.dict_put("Parent", parent.clone())?;
+
+        if let Some(page) = entry.page {
+            let mut dest = pdf.new_array()?;
+            let page_obj = pdf.find_page(page as i32)?;
+            dest.array_push(page_obj)?;
+            dest.array_push(pdf.new_name("Fit")?)?;
+            item.dict_put("Dest", dest)?;
+        }
+
+        let mut children = write_outline_entries(pdf, &item, &entry.children)?;
+        if let (Some(first), Some(last)) = (children.first(), children.last()) {
+            item.dict_put("First", first.clone())?;
+            item.dict_put("Last", last.clone())?;
+            // negative means the entry starts out closed
+            item.dict_put("Count", pdf.new_int(-(children.len() as i32))?)?;
+        }
+        link_siblings(&mut children)?;
+
+        items.push(item);
+    }
+    link_siblings(&mut items)?;
+    Ok(items)
+}
+
+// our entries go after whatever outline the PDF already has
+fn add_outline(
+    pdf: &mut PdfDocument,
+    info: &DocumentInfo,
+    tags: &Vector<TagDef>,
+) -> Result<(), Error> {
+    let entries = outline_entries(info, tags);
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut catalog = match pdf.trailer()?.get_dict("Root")? {
+        Some(catalog) => catalog,
+        None => return Ok(()),
+    };
+
+    let mut root = match catalog.get_dict("Outlines")? {
+        Some(root) => root,
+        None => {
+            let dict = pdf.new_dict()?;
+            let mut root = pdf.add_object(&dict)?;
+            root.dict_put("Type", pdf.new_name("Outlines")?)?;
+            catalog.dict_put("Outlines", root.clone())?;
+            root
+        }
+    };
+
+    let mut items = write_outline_entries(pdf, &root, &entries)?;
+
+    match root.get_dict("Last")? {
+        Some(mut old_last) => {
+            old_last.dict_put("Next", items[0].clone())?;
+            items[0].dict_put("Prev", old_last)?;
+        }
+        None => root.dict_put("First", items[0].clone())?,
+    }
+    root.dict_put("Last", items[items.len() - 1].clone())?;
+
+    let old_count = match root.get_dict("Count")? {
+        Some(count) => count.as_int().unwrap_or(0).abs(),
+        None => 0,
+    };
+    root.dict_put("Count", pdf.new_int(old_count + items.len() as i32)?)?;
+
+    Ok(())
+}
+
+fn add_annotation(
+    pdf: &mut PdfDocument,
+    page_obj: &mut PdfObject,
+    subtype: &str,
+    rect: &[f64; 4],
+    contents: Option<&str>,
+) -> Result<PdfObject, Error> {
+    let mut annot = pdf.new_dict()?;
+    annot.dict_put("Type", pdf.new_name("Annot")?)?;
+    annot.dict_put("Subtype", pdf.new_name(subtype)?)?;
+    annot.dict_put("Rect", numbers(pdf, rect)?)?;
+    annot.dict_put("F", pdf.new_int(4)?)?; // print it too
+    annot.dict_put("P", page_obj.clone())?;
+    if let Some(text) = contents {
+        annot.dict_put("Contents", pdf.new_string(text)?)?;
+    }
+    let annot = pdf.add_object(&annot)?;
+
+    match page_obj.get_dict("Annots")? {
+        Some(mut annots) => annots.array_push(annot.clone())?,
+        None => {
+            let mut annots = pdf.new_array()?;
+            annots.array_push(annot.clone())?;
+            page_obj.dict_put("Annots", annots)?;
+        }
+    }
+    Ok(annot)
+}
+
+// how a highlight looks, for viewers that don't work it out from /QuadPoints themselves
+// multiplied into the page, so the text shows through as it would under a highlighter pen
+fn highlight_appearance(
+    pdf: &mut PdfDocument,
+    rect: &[f64; 4],
+    quad_points: &[f64],
+    tag: &TagDef,
+) -> Result<PdfObject, Error> {
+    let (r, g, b, _) = tag.color().as_rgba8();
+    let mut content = format!(
+        "/H gs {:.3} {:.3} {:.3} rg\n",
+        r as f64 / 255.,
+        g as f64 / 255.,
+        b as f64 / 255.
+    );
+    for q in quad_points.chunks_exact(8) {
+        // round the quad from its top left corner, the bottom corners come in the opposite order to the top ones
+        content.push_str(&format!(
+            "{:.2} {:.2} m {:.2} {:.2} l {:.2} {:.2} l {:.2} {:.2} l h f\n",
+            q[0], q[1], q[2], q[3], q[6], q[7], q[4], q[5]
+        ));
+    }
+
+    let mut multiply = pdf.new_dict()?;
+    multiply.dict_put("Type", pdf.new_name("ExtGState")?)?;
+    multiply.dict_put("BM", pdf.new_name("Multiply")?)?;
+    let mut graphics_states = pdf.new_dict()?;
+    graphics_states.dict_put("H", multiply)?;
+    let mut resources = pdf.new_dict()?;
+    resources.dict_put("ExtGState", graphics_states)?;
+
+    let mut form = pdf.new_dict()?;
+    form.dict_put("Type", pdf.new_name("XObject")?)?;
+    form.dict_put("Subtype", pdf.new_name("Form")?)?;
+    form.dict_put("BBox", numbers(pdf, rect)?)?;
+    form.dict_put("Resources", resources)?;
+    let mut form = pdf.add_object(&form)?;
+    form.write_stream_buffer(&Buffer::from_bytes(content.as_bytes())?)?;
+    Ok(form)
+}
+
+fn add_page_annotations(
+    pdf: &mut PdfDocument,
+    info: &DocumentInfo,
+    tags: &Vector<TagDef>,
+    page: PageNum,
+    crop: bool,
+) -> Result<(), Error> {
+    let mut page_obj = pdf.find_page(page as i32)?;
+    let loaded_page = pdf.load_page(page as i32)?;
+    let space = match PageSpace::from_page(&page_obj, &loaded_page) {
+        Some(space) => space,
+        None => return Ok(()),
+    };
+
+    if crop {
+        let margins = info.page_margins_in_normalized_coords(page);
+        page_obj.dict_put("CropBox", numbers(pdf, &space.denormalize_rect(&margins))?)?;
+    }
+
+    if let Some(highlights) = info.highlights.get(&page) {
+        for h in highlights {
+            let mut quad_points = Vec::<f64>::new();
+            for q in &h.quads {
+                quad_points.extend_from_slice(&space.quad_points(q));
+            }
+            let rect = space.denormalize_rect(&h.bounds());
+            let tag = &tags[h.color % tags.len()];
+            let mut annot = add_annotation(pdf, &mut page_obj, "Highlight", &rect, None)?;
+            annot.dict_put("QuadPoints", numbers(pdf, &quad_points)?)?;
+            annot.dict_put("C", rgb(pdf, tag)?)?;
+            let mut appearances = pdf.new_dict()?;
+            appearances.dict_put("N", highlight_appearance(pdf, &rect, &quad_points, tag)?)?;
+            annot.dict_put("AP", appearances)?;
+        }
+    }
+
+    if let Some(rects) = info.color_inversion_rectangles.get(&page) {
+        for r in rects {
+            let mut annot = add_annotation(
+                pdf,
+                &mut page_obj,
+                "Square",
+                &space.denormalize_rect(r),
+                Some("Colours inverted when read in pdf-student"),
+            )?;
+            annot.dict_put("C", numbers(pdf, &[0.5, 0.5, 0.5])?)?;
+        }
+    }
+
+    if let Some(notes) = info.notes.get(&page) {
+        for note in notes {
+            // page notes are pinned to the top left corner of the page
+            let region = note
+                .region
+                .unwrap_or_else(|| Rect::new(0.02, 0.02, 0.06, 0.06));
+            let mut annot = add_annotation(
+                pdf,
+                &mut page_obj,
+                "Text",
+                &space.denormalize_rect(&region),
+                Some(&note.text),
+            )?;
+            annot.dict_put("Name", pdf.new_name("Comment")?)?;
+        }
+    }
+
+    Ok(())
+}
+
+// save a copy of the book for people who don't use pdf-student, the original file is left alone
+pub fn write_annotated_pdf(
+    source_path: &str,
+    info: &DocumentInfo,
+    tags: &Vector<TagDef>,
+    out_path: &Path,
+    crop: bool,
+) -> Result<(), Error> {
+    let mut pdf = PdfDocument::open(source_path)?;
+
+    add_outline(&mut pdf, info, tags)?;
+
+    let pages: BTreeSet<PageNum> = if crop {
+        (0..pdf.page_count()? as PageNum).collect()
+    } else {
+        info.highlights
+            .keys()
+            .chain(info.color_inversion_rectangles.keys())
+            .chain(info.notes.keys())
+            .copied()
+            .collect()
+    };
+    for page in pages {
+        add_page_annotations(&mut pdf, info, tags, page, crop)?;
+    }
+
+    pdf.save(&out_path.to_string_lossy())
+}
@@ -0,0 +1,67 @@
+use druid::widget::prelude::*;
+use druid::{commands as sys_cmds, LocalizedString, Selector, WidgetExt, WindowDesc};
+
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, Scroll};
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::doc_info_schema::parse_document_info;
+use crate::persistence::describe_age;
+use crate::{AppState, Fingerprint};
+
+pub const SHOW_BACKUPS: Selector<Fingerprint> = Selector::new("show-backups");
+pub const RESTORE_BACKUP: Selector<(Fingerprint, PathBuf)> = Selector::new("restore-backup");
+
+pub fn make_backups_window(
+    description: &str,
+    fingerprint: &Fingerprint,
+    backups: Vec<(SystemTime, PathBuf)>,
+) -> WindowDesc<AppState> {
+    let mut list = Flex::column().cross_axis_alignment(CrossAxisAlignment::Start);
+
+    if backups.is_empty() {
+        list.add_child(Label::new("No earlier versions have been kept yet.").padding(10.0));
+    }
+
+    for (time, path) in backups {
+        let summary = match fs::read_to_string(&path)
+            .ok()
+            .and_then(|serialized| parse_document_info(&serialized).ok())
+        {
+            Some(info) => format!(
+                "{}: {} tagged pages, {} bookmarks",
+                describe_age(time),
+                info.tags.values().filter(|bits| **bits != 0).count(),
+                info.bookmarks.len()
+            ),
+            None => format!("{}: unreadable", describe_age(time)),
+        };
+
+        let fingerprint = fingerprint.clone();
+        list.add_child(
+            Button::new(summary)
+                .on_click(move |ctx, _data: &mut AppState, _env| {
+                    ctx.submit_command(RESTORE_BACKUP.with((fingerprint.clone(), path.clone())));
+                    ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                })
+                .padding(5.0),
+        );
+    }
+
+    let ui = Flex::column()
+        .with_child(
+            Label::new(format!(
+                "Restore the tags and bookmarks of \"{}\" from an earlier version:",
+                description
+            ))
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding(10.0),
+        )
+        .with_flex_child(Scroll::new(list).vertical(), 1.);
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Earlier versions"))
+        .window_size((500., 400.))
+}
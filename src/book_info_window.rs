@@ -52,6 +52,13 @@ use druid::widget::{
 use std::convert::TryInto;
 use std::path::{Path, PathBuf};
 
+use crate::backups_window::SHOW_BACKUPS;
+use crate::fingerprint::CHOOSE_BOOK_TO_MERGE;
+use crate::prerequisites::{dependencies_summary, EDIT_PREREQUISITES, SHOW_READING_ORDER};
+use crate::reading_time::reading_summary;
+use crate::review::REVIEW_DUE_PAGES;
+use crate::library_search::SHOW_LIBRARY_SEARCH;
+use crate::tag_table::EDIT_TAG_TABLE;
 use crate::OPEN_BOOK_WITH_FINGERPRINT;
 use crate::SAVE_DOCUMENT_INFO;
 use crate::{AppState, Document, DocumentInfo, Fingerprint, RecentDocumentsWithLocations};
@@ -61,6 +68,8 @@ struct EditableInfoCard {
     info: DocumentInfo,
     being_edited: bool,
     selected: bool,
+    // which books this one needs and is needed by, worked out from the whole library
+    dependencies: String,
     // finished_editing: bool,
 }
 
@@ -68,13 +77,25 @@ struct EditableInfoCard {
 struct EditableInfoCardTransfer;
 
 impl ScopeTransfer for EditableInfoCardTransfer {
-    type In = ((Fingerprint, Vector<DocumentInfo>), DocumentInfo);
+    type In = (
+        (
+            Fingerprint,
+            Vector<DocumentInfo>,
+            HashMap<Fingerprint, DocumentInfo>,
+        ),
+        DocumentInfo,
+    );
 
     type State = EditableInfoCard;
 
-    fn read_input(&self, my_state: &mut Self::State, ((selected_fp, _), info): &Self::In) {
+    fn read_input(
+        &self,
+        my_state: &mut Self::State,
+        ((selected_fp, _, all_books), info): &Self::In,
+    ) {
         my_state.info = info.clone();
         my_state.selected = my_state.info.fingerprint == *selected_fp;
+        my_state.dependencies = dependencies_summary(info, all_books);
     }
 
     fn write_back_input(&self, my_state: &Self::State, external: &mut Self::In) {
@@ -110,7 +131,7 @@ impl ScopeTransfer for FilterableListTransfer {
     }
 }
 
-const BOOK_CARD_HEIGHT: f64 = 80.;
+const BOOK_CARD_HEIGHT: f64 = 100.;
 const LIST_WIDGET_ID: WidgetId = WidgetId::reserved(1);
 
 pub fn make_book_info_window(_state: &AppState, _doc_idx: usize) -> WindowDesc<AppState> {
@@ -162,14 +183,33 @@ pub fn make_book_info_window(_state: &AppState, _doc_idx: usize) -> WindowDesc<A
                             ));
                         },
                     ))
+                    .with_default_spacer()
+                    .with_child(Button::new("Search every book").on_click(
+                        |ctx, _data: &mut FilterableList, _env| {
+                            ctx.submit_command(SHOW_LIBRARY_SEARCH)
+                        },
+                    ))
+                    .with_default_spacer()
+                    .with_child(Button::new("Review due pages").on_click(
+                        |ctx, _data: &mut FilterableList, _env| {
+                            ctx.submit_command(REVIEW_DUE_PAGES)
+                        },
+                    ))
+                    .with_default_spacer()
+                    .with_child(Button::new("Reading order").on_click(
+                        |ctx, _data: &mut FilterableList, _env| {
+                            ctx.submit_command(SHOW_READING_ORDER)
+                        },
+                    ))
                     .with_default_spacer(),
             ))
             .with_flex_child(
                 Scroll::new(
                     List::new(|| {
                         Scope::from_function(
-                            |((_, _), doc_info)| EditableInfoCard {
+                            |((_, _, all_books), doc_info)| EditableInfoCard {
                                 selected: false,
+                                dependencies: dependencies_summary(&doc_info, &all_books),
                                 info: doc_info,
                                 being_edited: false,
                             },
@@ -203,19 +243,24 @@ pub fn make_book_info_window(_state: &AppState, _doc_idx: usize) -> WindowDesc<A
                                 Flex::row()
                                     .with_flex_child(
                                         Label::new(|item: &EditableInfoCard, _env: &_| {
-                                            format!(
+                                            let card = format!(
                                                 "[{}/{}] {}",
                                                 item.info.most_recent_page,
                                                 item.info.page_count,
                                                 item.info.description
-                                            )
+                                            );
+                                            let mut lines = vec![card];
+                                            lines.push(reading_summary(&item.info));
+                                            lines.push(item.dependencies.clone());
+                                            lines.retain(|line| !line.is_empty());
+                                            lines.join("\n")
                                         })
                                         .with_line_break_mode(LineBreaking::WordWrap)
                                         // .background(druid::theme::PRIMARY_DARK)
                                         .align_vertical(UnitPoint::LEFT)
                                         .padding(10.0)
                                         .expand()
-                                        .height(80.0)
+                                        .height(BOOK_CARD_HEIGHT)
                                         .on_click(|ctx, card: &mut EditableInfoCard, _env| {
                                             ctx.submit_command(
                                                 OPEN_BOOK_WITH_FINGERPRINT
@@ -239,6 +284,37 @@ pub fn make_book_info_window(_state: &AppState, _doc_idx: usize) -> WindowDesc<A
                                         |_ctx, data: &mut EditableInfoCard, _env| {
                                             data.being_edited = true
                                         },
+                                    ))
+                                    .with_child(Button::new("Earlier versions").on_click(
+                                        |ctx, data: &mut EditableInfoCard, _env| {
+                                            ctx.submit_command(
+                                                SHOW_BACKUPS.with(data.info.fingerprint.clone()),
+                                            )
+                                        },
+                                    ))
+                                    .with_child(Button::new("Tag names").on_click(
+                                        |ctx, data: &mut EditableInfoCard, _env| {
+                                            ctx.submit_command(
+                                                EDIT_TAG_TABLE
+                                                    .with(Some(data.info.fingerprint.clone())),
+                                            )
+                                        },
+                                    ))
+                                    .with_child(Button::new("Prerequisites").on_click(
+                                        |ctx, data: &mut EditableInfoCard, _env| {
+                                            ctx.submit_command(
+                                                EDIT_PREREQUISITES
+                                                    .with(data.info.fingerprint.clone()),
+                                            )
+                                        },
+                                    ))
+                                    .with_child(Button::new("Same book as...").on_click(
+                                        |ctx, data: &mut EditableInfoCard, _env| {
+                                            ctx.submit_command(
+                                                CHOOSE_BOOK_TO_MERGE
+                                                    .with(data.info.fingerprint.clone()),
+                                            )
+                                        },
                                     )),
                             ),
                         ) // Scope for info cards
@@ -247,13 +323,21 @@ pub fn make_book_info_window(_state: &AppState, _doc_idx: usize) -> WindowDesc<A
                         // Expose shared data with children data
                         |d: &FilterableList| {
                             (
-                                (d.selected_result.clone(), d.matches.clone()),
+                                (
+                                    d.selected_result.clone(),
+                                    d.matches.clone(),
+                                    d.all_books.clone(),
+                                ),
                                 d.matches.clone(),
                             )
                         },
                         move |d: &mut FilterableList,
-                              ((_selected_fingerprint, original_list), modified_list): (
-                            (Fingerprint, Vector<DocumentInfo>),
+                              ((_selected_fingerprint, original_list, _all_books), modified_list): (
+                            (
+                                Fingerprint,
+                                Vector<DocumentInfo>,
+                                HashMap<Fingerprint, DocumentInfo>,
+                            ),
                             Vector<DocumentInfo>,
                         )| {
                             d.find_matches();
@@ -289,7 +373,13 @@ impl FilterableList {
 
         for fp in self.access_history.iter().rev() {
             if let Some(info) = &self.all_books.get(fp) {
-                if fuzzy_match(&info.description.to_lowercase(), &search) {
+                // notes are searched too, so a book can be found by what was jotted in it
+                let mut candidate = info.description.to_lowercase();
+                for note in info.notes.values().flatten() {
+                    candidate.push(' ');
+                    candidate.push_str(&note.text.to_lowercase());
+                }
+                if fuzzy_match(&candidate, &search) {
                     self.matches.push_back((*info).clone());
                 }
             }
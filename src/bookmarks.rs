@@ -0,0 +1,184 @@
+use druid::im::Vector;
+use druid::widget::prelude::*;
+use druid::widget::{
+    Button, Flex, Label, LineBreaking, List, Scope, ScopeTransfer, Scroll, TextBox,
+};
+use druid::{commands as sys_cmds, lens, Lens, LocalizedString, Selector, WidgetExt, WindowDesc};
+
+use crate::export::page_label;
+use crate::pdf_view::{PdfViewState, WindowMode, SET_WINDOW_MODE};
+use crate::{AppState, DocumentInfo, Fingerprint, PageNum};
+
+// opens the dialog for naming a bookmark on the given page
+pub const ADD_NAMED_BOOKMARK: Selector<(Fingerprint, PageNum)> =
+    Selector::new("add-named-bookmark");
+pub const SAVE_NAMED_BOOKMARK: Selector<(Fingerprint, String, PageNum)> =
+    Selector::new("save-named-bookmark");
+// sent from the bookmarks panel to its own window
+pub const GO_TO_BOOKMARK: Selector<PageNum> = Selector::new("go-to-bookmark");
+pub const REMOVE_BOOKMARK: Selector<String> = Selector::new("remove-bookmark");
+
+// single letters are the bookmarks made by typing on a page, and jumped to the same way
+pub fn is_letter_shortcut(name: &str) -> bool {
+    name.chars().count() == 1
+}
+
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct BookmarkRow {
+    name: String,
+    page: PageNum,
+    page_label: String,
+}
+
+// in page order, so the list reads like a table of contents
+pub fn bookmark_rows(info: &DocumentInfo) -> Vector<BookmarkRow> {
+    let mut bookmarks: Vec<(&String, &PageNum)> = info.bookmarks.iter().collect();
+    bookmarks.sort_by(|(a_name, a_page), (b_name, b_page)| {
+        a_page.cmp(b_page).then_with(|| a_name.cmp(b_name))
+    });
+    bookmarks
+        .into_iter()
+        .map(|(name, page)| BookmarkRow {
+            name: name.clone(),
+            page: *page,
+            page_label: page_label(info, *page),
+        })
+        .collect()
+}
+
+fn make_bookmark_row() -> impl Widget<BookmarkRow> {
+    Flex::row()
+        .with_flex_child(
+            Label::new(|row: &BookmarkRow, _env: &_| format!("{}  {}", row.name, row.page_label))
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+            1.,
+        )
+        .with_child(
+            Button::new("Go").on_click(|ctx, row: &mut BookmarkRow, _env| {
+                ctx.submit_command(GO_TO_BOOKMARK.with(row.page))
+            }),
+        )
+        .with_child(
+            Button::new("Delete").on_click(|ctx, row: &mut BookmarkRow, _env| {
+                ctx.submit_command(REMOVE_BOOKMARK.with(row.name.clone()))
+            }),
+        )
+        .padding((5., 2.))
+}
+
+// shown beside the pages in WindowMode::Bookmarks
+pub fn make_bookmarks_panel() -> impl Widget<PdfViewState> {
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_flex_child(Label::new("Bookmarks").expand_width(), 1.)
+                .with_child(Button::new("Bookmark this page...").on_click(
+                    |ctx, data: &mut PdfViewState, _env| {
+                        ctx.submit_command(ADD_NAMED_BOOKMARK.with((
+                            data.document_info.fingerprint.clone(),
+                            data.page_number_or_link_target(),
+                        )))
+                    },
+                ))
+                .with_child(
+                    Button::new("Close").on_click(|ctx, _data: &mut PdfViewState, _env| {
+                        ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Normal))
+                    }),
+                )
+                .padding(5.),
+        )
+        .with_flex_child(
+            Scroll::new(List::new(make_bookmark_row))
+                .vertical()
+                // read only, rows change the bookmarks through commands
+                .lens(lens::Map::new(
+                    |data: &PdfViewState| bookmark_rows(&data.document_info),
+                    |_data: &mut PdfViewState, _rows: Vector<BookmarkRow>| {},
+                )),
+            1.,
+        )
+        .fix_width(300.)
+}
+
+#[derive(Clone, Data, Lens)]
+struct BookmarkNamer {
+    fingerprint: Fingerprint,
+    page: PageNum,
+    name: String,
+}
+
+struct BookmarkNamerTransfer;
+
+impl ScopeTransfer for BookmarkNamerTransfer {
+    type In = AppState;
+    type State = BookmarkNamer;
+
+    fn read_input(&self, _my_state: &mut Self::State, _external: &Self::In) {}
+
+    fn write_back_input(&self, _my_state: &Self::State, _external: &mut Self::In) {}
+}
+
+pub fn make_bookmark_name_window(
+    data: &AppState,
+    fingerprint: Fingerprint,
+    page: PageNum,
+) -> WindowDesc<AppState> {
+    let heading = match data.all_local_documents_info.get(&fingerprint) {
+        Some(info) => format!(
+            "Name for a bookmark on {} of \"{}\", such as \"ch3-lemma\". Naming it after an existing bookmark moves that one here.",
+            page_label(info, page),
+            info.description
+        ),
+        None => String::new(),
+    };
+
+    let ui = Scope::from_function(
+        move |_app_state: AppState| BookmarkNamer {
+            fingerprint: fingerprint.clone(),
+            page,
+            name: String::new(),
+        },
+        BookmarkNamerTransfer,
+        Flex::column()
+            .with_child(
+                Label::new(heading)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(10.0)
+                    .expand_width(),
+            )
+            .with_child(
+                TextBox::new()
+                    .with_placeholder("Bookmark name")
+                    .lens(BookmarkNamer::name)
+                    .expand_width()
+                    .padding(10.0),
+            )
+            .with_child(
+                Flex::row()
+                    .with_child(Button::new("Save").on_click(
+                        |ctx, data: &mut BookmarkNamer, _env| {
+                            if !data.name.trim().is_empty() {
+                                ctx.submit_command(SAVE_NAMED_BOOKMARK.with((
+                                    data.fingerprint.clone(),
+                                    data.name.trim().to_string(),
+                                    data.page,
+                                )));
+                            }
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        },
+                    ))
+                    .with_default_spacer()
+                    .with_child(Button::new("Cancel").on_click(
+                        |ctx, _data: &mut BookmarkNamer, _env| {
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        },
+                    ))
+                    .padding(10.0),
+            ),
+    );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Named bookmark"))
+        .window_size((400., 220.))
+}
@@ -0,0 +1,97 @@
+use serde_json::{json, Map, Value};
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::DocumentInfo;
+
+// bump this whenever a field is added to `DocumentInfo`, or one changes, even if `#[serde(default)]` copes with
+// it being missing: an older build that read the file as its own version would drop the field on its next save,
+// where a newer version number makes it refuse to touch the file instead
+// 0: files from before the version was recorded
+// 1: `schema_version`
+// 2: `highlights`
+// 3: `notes`
+// 4: `pdf_annotations_imported`
+// 5: `tag_table`
+// 6: `reading_time` and `daily_reading_time`
+// 7: `reviews`
+// 8: `prerequisites`
+pub const CURRENT_SCHEMA_VERSION: u32 = 8;
+
+type Migration = fn(&mut Map<String, Value>);
+
+// (version, step) pairs, each step upgrading a file of that version to the next one
+// only needed when existing data has to be rearranged, new fields with a default are filled in by serde
+const MIGRATIONS: &[(u32, Migration)] = &[];
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Unparseable(String),
+    TooNew(u32), // written by a later version of this program, which might store things we'd silently drop
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Unparseable(e) => write!(f, "unable to parse book info: {}", e),
+            SchemaError::TooNew(version) => write!(
+                f,
+                "book info uses format version {}, newer than the {} this program understands",
+                version, CURRENT_SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+pub fn parse_document_info(serialized: &str) -> Result<DocumentInfo, SchemaError> {
+    let mut value: Value = serde_json::from_str(serialized)
+        .map_err(|e| SchemaError::Unparseable(e.to_string()))?;
+
+    let info = value
+        .as_object_mut()
+        .ok_or_else(|| SchemaError::Unparseable("not a JSON object".to_string()))?;
+
+    let version = info
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32;
+
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(SchemaError::TooNew(version));
+    }
+
+    for (from, migration) in MIGRATIONS {
+        if *from >= version {
+            migration(info);
+        }
+    }
+    info.insert("schema_version".to_string(), json!(CURRENT_SCHEMA_VERSION));
+
+    serde_json::from_value(value).map_err(|e| SchemaError::Unparseable(e.to_string()))
+}
+
+// move a file we couldn't parse out of the way rather than overwriting it, so whatever was in it can still be rescued by hand
+// "<fingerprint>.json" becomes "<fingerprint>.unreadable.json", or "<fingerprint>.unreadable.<seconds since 1970>.json" if that's taken
+pub fn set_aside_unreadable(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?.to_string();
+
+    let mut aside = path.with_file_name(format!("{}.unreadable.json", stem));
+    if aside.exists() {
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        aside = path.with_file_name(format!("{}.unreadable.{}.json", stem, secs));
+    }
+
+    match fs::rename(path, &aside) {
+        Ok(()) => Some(aside),
+        Err(e) => {
+            println!("unable to set aside unreadable file {:?}: {}", path, e);
+            None
+        }
+    }
+}
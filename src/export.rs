@@ -0,0 +1,168 @@
+use druid::im::Vector;
+use druid::FileSpec;
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tag_table::{tag_table, TagDef};
+use crate::{AppState, DocumentInfo, PageNum};
+
+pub const MARKDOWN_FILE: FileSpec = FileSpec::new("Markdown", &["md"]);
+pub const ORG_FILE: FileSpec = FileSpec::new("Org-mode", &["org"]);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Org,
+}
+
+impl ExportFormat {
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "--export-markdown" => Some(ExportFormat::Markdown),
+            "--export-org" => Some(ExportFormat::Org),
+            _ => None,
+        }
+    }
+
+    // the save dialog offers both, so go by whichever extension was picked
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "org" => Some(ExportFormat::Org),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Org => "org",
+        }
+    }
+
+    fn heading(self, level: usize, text: &str) -> String {
+        let marker = match self {
+            ExportFormat::Markdown => "#",
+            ExportFormat::Org => "*",
+        };
+        format!("{} {}\n\n", marker.repeat(level), text)
+    }
+
+    fn quote(self, text: &str) -> String {
+        match self {
+            ExportFormat::Markdown => format!("> {}\n\n", text),
+            ExportFormat::Org => format!("#+BEGIN_QUOTE\n{}\n#+END_QUOTE\n\n", text),
+        }
+    }
+}
+
+// the number printed on the page, or the PDF's own page number for front matter before page 1
+pub fn page_label(info: &DocumentInfo, page: PageNum) -> String {
+    let printed = page as i32 + 1 - info.page_offset;
+    if printed >= 1 {
+        format!("p. {}", printed)
+    } else {
+        format!("PDF page {}", page + 1)
+    }
+}
+
+// `tags` is the book's tag table, from tag_table::tag_table
+pub fn export_document_info(
+    info: &DocumentInfo,
+    tags: &Vector<TagDef>,
+    format: ExportFormat,
+) -> String {
+    let mut out = format.heading(1, &info.description);
+
+    if !info.bookmarks.is_empty() {
+        out.push_str(&format.heading(2, "Bookmarks"));
+        let mut bookmarks: Vec<(&String, &PageNum)> = info.bookmarks.iter().collect();
+        bookmarks.sort();
+        for (letter, page) in bookmarks {
+            out.push_str(&format!("- {}: {}\n", letter, page_label(info, *page)));
+        }
+        out.push('\n');
+    }
+
+    let mut tagged_pages: Vec<(&PageNum, &u16)> =
+        info.tags.iter().filter(|(_, bits)| **bits != 0).collect();
+    if !tagged_pages.is_empty() {
+        tagged_pages.sort();
+        out.push_str(&format.heading(2, "Tagged pages"));
+        for (tag, def) in tags.iter().enumerate() {
+            let pages: Vec<String> = tagged_pages
+                .iter()
+                .filter(|(_, bits)| **bits & (1 << tag) != 0)
+                .map(|(page, _)| page_label(info, **page))
+                .collect();
+            if !pages.is_empty() {
+                out.push_str(&format!(
+                    "- {} (tag {}): {}\n",
+                    def.name,
+                    tag,
+                    pages.join(", ")
+                ));
+            }
+        }
+        out.push('\n');
+    }
+
+    let annotated_pages: BTreeSet<&PageNum> =
+        info.highlights.keys().chain(info.notes.keys()).collect();
+    if !annotated_pages.is_empty() {
+        out.push_str(&format.heading(2, "Notes and highlights"));
+        for page in annotated_pages {
+            out.push_str(&format.heading(3, &page_label(info, *page)));
+            if let Some(notes) = info.notes.get(page) {
+                for note in notes {
+                    out.push_str(note.text.trim());
+                    out.push_str("\n\n");
+                }
+            }
+            if let Some(highlights) = info.highlights.get(page) {
+                for highlight in highlights {
+                    out.push_str(&format.quote(&highlight.text));
+                }
+            }
+        }
+    }
+
+    out
+}
+
+pub fn export_to_file(info: &DocumentInfo, tags: &Vector<TagDef>, path: &Path) {
+    let format = ExportFormat::from_path(path).unwrap_or(ExportFormat::Markdown);
+    if let Err(e) = fs::write(path, export_document_info(info, tags, format)) {
+        println!("Error exporting book info to {:?}: {}", path, e);
+    }
+}
+
+// `pdf-student --export-markdown book.pdf [output.md]`, or `--export-org`, the output defaults to the book's name with a ".md" or ".org" extension
+pub fn export_from_command_line(format: ExportFormat, args: &[String]) {
+    let pdf_path = match args.first() {
+        Some(path) => Path::new(path),
+        None => {
+            println!("Usage: pdf-student --export-markdown|--export-org <book.pdf> [output file]");
+            return;
+        }
+    };
+    let out_path = match args.get(1) {
+        Some(path) => PathBuf::from(path),
+        None => pdf_path.with_extension(format.extension()),
+    };
+
+    let mut state = AppState::new();
+    if let Some(doc_idx) = state.load_file(pdf_path) {
+        let fingerprint = state.loaded_documents[doc_idx].fingerprint.clone();
+        let info = state.all_local_documents_info[&fingerprint].clone();
+        let tags = tag_table(&info, &state.preferences.default_tag_table);
+
+        if let Err(e) = fs::write(&out_path, export_document_info(&info, &tags, format)) {
+            println!("Error writing {:?}: {}", out_path, e);
+        } else {
+            println!("Exported to {:?}", out_path);
+        }
+    }
+}
@@ -1,39 +1,60 @@
 use druid::widget::prelude::*;
 use druid::{
     Affine, AppLauncher, Color, Command, FileDialogOptions, FileSpec, FontDescriptor, FontStyle,
-    FontWeight, Handled, Lens, LocalizedString, Menu, MenuItem, MouseButton, MouseEvent, Point,
-    Rect, Selector, SysMods, Target, TextLayout, Vec2, WindowDesc, WindowId,
+    FontWeight, Handled, Lens, LensExt, LocalizedString, Menu, MenuItem, MouseButton, MouseEvent,
+    Point, Rect, Selector, SysMods, Target, TextLayout, Vec2, WindowDesc, WindowId,
 };
 
 use druid::widget::{
-    Align, Axis, Container, Controller, ControllerHost, Flex, Label, LineBreaking, Padding,
-    Painter, RadioGroup, Scope, ScopeTransfer, SizedBox, Slider, Split, TextBox, ViewSwitcher,
-    WidgetExt,
+    Align, Axis, Checkbox, Container, Controller, ControllerHost, Flex, Label, LineBreaking,
+    Padding, Painter, RadioGroup, Scope, ScopeTransfer, SizedBox, Slider, Split, TextBox,
+    ViewSwitcher, WidgetExt,
 };
 
 use druid::keyboard_types::Key;
 
 use crate::pdf_view::{PdfViewState, WindowMode, SET_WINDOW_MODE};
+use crate::text_match::FindOptions;
 
 pub const FOCUS_FIND_TEXTBOX: Selector = Selector::new("focus-find-textbox");
 pub const START_SEARCH: Selector = Selector::new("start-search");
 pub const START_GOTO: Selector = Selector::new("start-goto");
+pub const FOCUS_TAG_QUERY_TEXTBOX: Selector = Selector::new("focus-tag-query-textbox");
 
 struct FindController;
 struct GotoController;
 struct OffsetController;
+struct TagQueryController;
 
 pub fn make_find_ui() -> impl Widget<PdfViewState> {
     SizedBox::new(
         Flex::row()
-            .with_child(
-                Label::new("Find exact phrase, case insensitively: ").controller(FindController),
-            )
+            .with_child(Label::new("Find: ").controller(FindController))
             .with_child(
                 TextBox::new()
                     .lens(PdfViewState::find_goal)
                     .controller(FindController),
-            ),
+            )
+            .with_default_spacer()
+            .with_child(
+                Checkbox::new("regex").lens(PdfViewState::find_options.then(FindOptions::regex)),
+            )
+            .with_child(
+                Checkbox::new("whole words")
+                    .lens(PdfViewState::find_options.then(FindOptions::whole_word)),
+            )
+            .with_child(
+                Checkbox::new("match case")
+                    .lens(PdfViewState::find_options.then(FindOptions::case_sensitive)),
+            )
+            .with_child(
+                Checkbox::new("match accents")
+                    .lens(PdfViewState::find_options.then(FindOptions::match_diacritics)),
+            )
+            .with_default_spacer()
+            .with_child(Label::new(|data: &PdfViewState, _env: &_| {
+                data.search_status()
+            })),
     )
     .height(50.)
 }
@@ -66,6 +87,24 @@ pub fn make_goto_ui() -> impl Widget<PdfViewState> {
     .height(50.)
 }
 
+pub fn make_tag_query_ui() -> impl Widget<PdfViewState> {
+    SizedBox::new(
+        Flex::row()
+            .with_child(Label::new("Pages whose tags match: "))
+            .with_child(
+                TextBox::new()
+                    .with_placeholder("3 & !5")
+                    .lens(PdfViewState::tag_query_text)
+                    .controller(TagQueryController),
+            )
+            .with_default_spacer()
+            .with_child(Label::new(|data: &PdfViewState, _env: &_| {
+                data.tag_query_status()
+            })),
+    )
+    .height(50.)
+}
+
 use crate::pdf_text_widget::SHOW_GIVEN_PAGE;
 
 impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for GotoController {
@@ -250,16 +289,23 @@ impl<W: Widget<PdfViewState>> Controller <PdfViewState, W> for FindController {
             Event::KeyDown(e) => {
                 if e.key == Key::Escape {
                     ctx.resign_focus();
-                    data.search_results.borrow_mut().clear();
+                    data.stop_search();
                     data.window_mode = WindowMode::Normal;
                     ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Normal));
                     ctx.set_handled();
                 } else if e.mods.ctrl() && e.key == Key::Character("g".to_string()) {
                     ctx.resign_focus();
-                    data.search_results.borrow_mut().clear();
+                    data.stop_search();
                     data.window_mode = WindowMode::Goto;
                     ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Goto));
                     ctx.set_handled();
+                } else if e.key == Key::ArrowDown || e.key == Key::ArrowUp {
+                    // through the results panel's list, the text box has only the one line
+                    data.step_search_hit(ctx.window_id(), e.key == Key::ArrowDown);
+                    ctx.set_handled();
+                } else if e.key == Key::Enter || e.key == Key::F3 {
+                    data.step_search_hit(ctx.window_id(), !e.mods.shift());
+                    ctx.set_handled();
                 } else {
                     child.event(ctx, event, data, env);
                 }
@@ -293,9 +339,73 @@ impl<W: Widget<PdfViewState>> Controller <PdfViewState, W> for FindController {
         data: &PdfViewState,
         env: &Env,
     ) {
-        if data.find_goal != old_data.find_goal {
-            ctx.submit_command(START_SEARCH); // handled by the scrollbar so it can request animation frames as it's redrawn to show search progress
+        if data.find_goal != old_data.find_goal || data.find_options != old_data.find_options {
+            ctx.submit_command(START_SEARCH); // handled by the scrollbar, which redraws as the search thread's results come in
         }
         child.update(ctx, old_data, data, env);
     }
 }
+
+impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for TagQueryController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut PdfViewState,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(FOCUS_TAG_QUERY_TEXTBOX) => {
+                ctx.request_focus();
+            }
+            Event::KeyDown(e) => {
+                if e.key == Key::Escape {
+                    // forget the query, so PgUp / PgDown go back to stepping through every tagged page
+                    ctx.resign_focus();
+                    data.tag_query_text.clear();
+                    data.window_mode = WindowMode::Normal;
+                    ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Normal));
+                    ctx.set_handled();
+                } else if e.key == Key::Enter {
+                    // keep the query and go to the first match from here on
+                    ctx.resign_focus();
+                    if data.tag_query().map_or(false, |q| {
+                        !q.matches(data.document_info.tag_bits(data.page_number))
+                    }) {
+                        if let Some(page) = data.next_tagged_page(data.page_number, true) {
+                            data.show_page(ctx.window_id(), page);
+                        }
+                    }
+                    data.window_mode = WindowMode::Normal;
+                    ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Normal));
+                    ctx.set_handled();
+                } else if e.key == Key::PageDown || e.key == Key::PageUp {
+                    if let Some(page) =
+                        data.next_tagged_page(data.page_number, e.key == Key::PageDown)
+                    {
+                        data.show_page(ctx.window_id(), page);
+                    }
+                    ctx.set_handled();
+                } else {
+                    child.event(ctx, event, data, env);
+                }
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        child: &mut W,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &PdfViewState,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.submit_command(FOCUS_TAG_QUERY_TEXTBOX);
+        }
+        child.lifecycle(ctx, event, data, env);
+    }
+}
@@ -0,0 +1,340 @@
+use druid::im::{HashMap, Vector};
+use druid::widget::prelude::*;
+use druid::widget::{Button, Flex, Label, LineBreaking, List, Scope, ScopeTransfer, Scroll};
+use druid::{
+    commands as sys_cmds, Lens, LocalizedString, Selector, UnitPoint, WidgetExt, WindowDesc,
+};
+
+use mupdf::pdf::{PdfDocument, PdfObject};
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::pdf_annotations::{inherited, PAGE_TREE_DEPTH};
+use crate::persistence::write_atomically;
+use crate::{AppState, Fingerprint, PageNum};
+
+// the user's answer to whether a file is the same book as the one whose record it ran into, true for the same book
+pub const RESOLVE_FINGERPRINT_COLLISION: Selector<(FingerprintCollision, bool)> =
+    Selector::new("resolve-fingerprint-collision");
+// opens the list of other books a book can be merged into, for two records that turn out to be the same book
+pub const CHOOSE_BOOK_TO_MERGE: Selector<Fingerprint> = Selector::new("choose-book-to-merge");
+// the record to fold in, and the one it's folded into
+pub const MERGE_BOOKS: Selector<(Fingerprint, Fingerprint)> = Selector::new("merge-books");
+
+// kept in the syncable directory next to the books' info files, so every machine files a book under the same record
+pub const FINGERPRINT_ALIASES_FILE: &str = "fingerprint_aliases.json";
+
+// IDs some PDF writers give every document they make, so they say nothing about which book it is
+// fingerprinting PDFs https://www.seanh.cc/2017/11/22/pdf-fingerprinting/
+const SHARED_PDF_IDS: [&str; 1] = ["67C6697351FF4AEC29CDBAABF2FBE346"];
+
+// enough to tell apart different books from the same tool, few enough that opening a big book stays quick
+const FINGERPRINT_PAGES: i32 = 3;
+
+// a file's fingerprint -> the fingerprint of the record it's kept under, for files whose own fingerprint isn't
+// the record's, which is books known from before fingerprints covered the content, and files merged into another book's record
+pub type FingerprintAliases = HashMap<Fingerprint, Fingerprint>;
+
+#[derive(Clone, Debug)]
+pub struct FileIdentity {
+    // from the PDF's ID, its page count and its first pages' content
+    pub fingerprint: Fingerprint,
+    // what the book was known by before, the ID alone or a hash of the start of the file, so older records are still found
+    pub legacy: Fingerprint,
+    pub page_count: PageNum,
+}
+
+// two different-looking files with the same legacy fingerprint, waiting for the user to say if they're the same book
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct FingerprintCollision {
+    pub path: String,
+    pub fingerprint: Fingerprint,
+    // the record it ran into
+    pub record: Fingerprint,
+    pub summary: String,
+}
+
+impl FingerprintCollision {
+    pub fn is_about(&self, other: &FingerprintCollision) -> bool {
+        self.fingerprint == other.fingerprint
+    }
+}
+
+fn pdf_id(document: &PdfDocument) -> Option<String> {
+    let trailer = document.trailer().ok()?;
+    let id = trailer.get_dict("ID").ok()??.resolve().ok()??;
+    let first = id.get_array(0).ok()??.to_string();
+    // written out as a hex string, "<...>"
+    if first.len() < 3 {
+        return None;
+    }
+    let first = first[1..first.len() - 1].to_string();
+    if SHARED_PDF_IDS.contains(&first.as_str()) {
+        None
+    } else {
+        Some(first)
+    }
+}
+
+fn hash_of_start_of_file(path_string: &str) -> String {
+    let mut buf = [0; 1024];
+
+    match File::open(path_string) {
+        Ok(mut file) => {
+            if let Err(e) = file.read_exact(&mut buf) {
+                println!(
+                    "error reading PDF file to compute document fingerprint: {}",
+                    e
+                );
+            }
+        }
+        Err(e) => println!(
+            "error opening PDF file to compute document fingerprint: {}",
+            e
+        ),
+    }
+    format!("{:x}", md5::compute(&buf[..]))
+}
+
+// the names of the images and forms a content stream draws, in the order it draws them
+fn drawn_xobjects(stream: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(stream);
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    tokens
+        .windows(2)
+        .filter(|pair| pair[1] == "Do")
+        .filter_map(|pair| pair[0].strip_prefix('/'))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// a page's content is one stream or an array of them
+fn consume_contents(contents: &PdfObject, context: &mut md5::Context, drawn: &mut Vec<String>) {
+    if contents.is_array().unwrap_or(false) {
+        for idx in 0..contents.len().unwrap_or(0) {
+            if let Ok(Some(part)) = contents.get_array(idx as i32) {
+                consume_contents(&part, context, drawn);
+            }
+        }
+    } else if let Ok(stream) = contents.read_stream() {
+        context.consume(&stream);
+        drawn.extend(drawn_xobjects(&stream));
+    }
+}
+
+// scanned books' pages are each just "draw this image", so the same content can be different books
+// the images and forms the page draws are hashed too
+fn consume_xobjects(page: &PdfObject, drawn: &[String], context: &mut md5::Context) {
+    let xobjects = inherited(page, "Resources", PAGE_TREE_DEPTH)
+        .and_then(|resources| resources.get_dict("XObject").ok().flatten());
+    if let Some(xobjects) = xobjects {
+        for name in drawn {
+            if let Ok(Some(xobject)) = xobjects.get_dict(name.as_str()) {
+                if let Ok(stream) = xobject.read_stream() {
+                    context.consume(&stream);
+                }
+            }
+        }
+    }
+}
+
+// the streams are hashed once decompressed, so a copy saved with different compression is still the same book
+fn content_fingerprint(document: &PdfDocument, id: Option<&str>, page_count: PageNum) -> String {
+    let mut context = md5::Context::new();
+    context.consume(id.unwrap_or("").as_bytes());
+    context.consume(page_count.to_string().as_bytes());
+    for page_num in 0..FINGERPRINT_PAGES.min(page_count as i32) {
+        let page = match document.find_page(page_num) {
+            Ok(page) => page,
+            Err(_) => continue,
+        };
+        let mut drawn = Vec::<String>::new();
+        if let Ok(Some(contents)) = page.get_dict("Contents") {
+            consume_contents(&contents, &mut context, &mut drawn);
+        }
+        consume_xobjects(&page, &drawn, &mut context);
+    }
+    format!("{:x}", context.compute())
+}
+
+pub fn identify(document: &PdfDocument, path_string: &str) -> FileIdentity {
+    let id = pdf_id(document);
+    let page_count = document.page_count().unwrap_or(0) as PageNum;
+    FileIdentity {
+        fingerprint: content_fingerprint(document, id.as_deref(), page_count),
+        legacy: id.unwrap_or_else(|| hash_of_start_of_file(path_string)),
+        page_count,
+    }
+}
+
+pub fn aliases_path(syncable_dir: &str) -> PathBuf {
+    let mut path = PathBuf::from(syncable_dir);
+    path.push(FINGERPRINT_ALIASES_FILE);
+    path
+}
+
+pub fn is_aliases_file(path: &Path) -> bool {
+    path.file_name()
+        .map_or(false, |name| name == FINGERPRINT_ALIASES_FILE)
+}
+
+pub fn load_aliases(path: &Path) -> FingerprintAliases {
+    match fs::read_to_string(path) {
+        Ok(serialized) => match serde_json::from_str(&serialized) {
+            Ok(aliases) => aliases,
+            Err(e) => {
+                println!("Error reading fingerprint aliases {:?}: {}", path, e);
+                FingerprintAliases::new()
+            }
+        },
+        Err(_) => FingerprintAliases::new(),
+    }
+}
+
+// entries are only ever added, ours win if another machine filed the same file differently
+pub fn merge_aliases(ours: &FingerprintAliases, theirs: &FingerprintAliases) -> FingerprintAliases {
+    ours.clone().union(theirs.clone())
+}
+
+pub fn save_aliases(path: &Path, aliases: &FingerprintAliases) -> FingerprintAliases {
+    let merged = merge_aliases(aliases, &load_aliases(path));
+    let serialized = serde_json::to_string(&merged).unwrap();
+    if let Err(e) = write_atomically(path, &serialized[..]) {
+        println!("Error writing file: {}", e);
+    }
+    merged
+}
+
+fn make_collision_row() -> impl Widget<FingerprintCollision> {
+    Flex::row()
+        .with_flex_child(
+            Label::new(|collision: &FingerprintCollision, _env: &_| collision.summary.clone())
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .align_vertical(UnitPoint::LEFT)
+                .padding(10.0)
+                .expand_width(),
+            1.,
+        )
+        .with_child(Button::new("Same book").on_click(
+            |ctx, collision: &mut FingerprintCollision, _env| {
+                ctx.submit_command(RESOLVE_FINGERPRINT_COLLISION.with((collision.clone(), true)))
+            },
+        ))
+        .with_default_spacer()
+        .with_child(Button::new("Different book").on_click(
+            |ctx, collision: &mut FingerprintCollision, _env| {
+                ctx.submit_command(RESOLVE_FINGERPRINT_COLLISION.with((collision.clone(), false)))
+            },
+        ))
+        .with_default_spacer()
+}
+
+pub fn make_fingerprint_collisions_window() -> WindowDesc<AppState> {
+    let ui = Flex::column()
+        .with_child(
+            Label::new(
+                "These files look like books PDF Student already knows, but their contents differ. The same book shares its tags, bookmarks and notes with the one it's known as; a different book gets its own, starting as a copy of what they've shared until now. Either way the file is opened afterwards.",
+            )
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding(10.0),
+        )
+        .with_flex_child(
+            Scroll::new(List::new(make_collision_row))
+                .vertical()
+                .lens(AppState::fingerprint_collisions),
+            1.,
+        );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Same book or different?"))
+        .window_size((700., 400.))
+}
+
+#[derive(Clone, Data, Lens, PartialEq)]
+struct MergeCandidate {
+    from: Fingerprint,
+    into: Fingerprint,
+    description: String,
+}
+
+// the list is worked out when the window opens, nothing is written back
+struct MergeCandidatesTransfer;
+
+impl ScopeTransfer for MergeCandidatesTransfer {
+    type In = AppState;
+    type State = Vector<MergeCandidate>;
+
+    fn read_input(&self, _my_state: &mut Self::State, _external: &Self::In) {}
+
+    fn write_back_input(&self, _my_state: &Self::State, _external: &mut Self::In) {}
+}
+
+fn make_merge_row() -> impl Widget<MergeCandidate> {
+    Flex::row()
+        .with_flex_child(
+            Label::new(|candidate: &MergeCandidate, _env: &_| candidate.description.clone())
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .align_vertical(UnitPoint::LEFT)
+                .padding(10.0)
+                .expand_width(),
+            1.,
+        )
+        .with_child(Button::new("Same book").on_click(
+            |ctx, candidate: &mut MergeCandidate, _env| {
+                ctx.submit_command(
+                    MERGE_BOOKS.with((candidate.from.clone(), candidate.into.clone())),
+                );
+                ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+            },
+        ))
+        .with_default_spacer()
+}
+
+pub fn make_merge_books_window(data: &AppState, fingerprint: Fingerprint) -> WindowDesc<AppState> {
+    let description = data
+        .all_local_documents_info
+        .get(&fingerprint)
+        .map(|info| info.description.clone())
+        .unwrap_or_default();
+    let mut candidates: Vec<MergeCandidate> = data
+        .all_local_documents_info
+        .values()
+        .filter(|other| other.fingerprint != fingerprint)
+        .map(|other| MergeCandidate {
+            from: fingerprint.clone(),
+            into: other.fingerprint.clone(),
+            description: other.description.clone(),
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.description.cmp(&b.description));
+    let candidates: Vector<MergeCandidate> = candidates.into_iter().collect();
+
+    let ui = Scope::from_function(
+        move |_app_state: AppState| candidates.clone(),
+        MergeCandidatesTransfer,
+        Flex::column()
+            .with_child(
+                Label::new(format!(
+                    "Which book is \"{}\" the same as? Its tags, bookmarks, notes and reading time are added to that book's, which keeps its own wherever the two differ, and its files are opened as that book from then on.",
+                    description
+                ))
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .padding(10.0)
+                .expand_width(),
+            )
+            .with_flex_child(Scroll::new(List::new(make_merge_row)).vertical(), 1.)
+            .with_child(
+                Button::new("Cancel")
+                    .on_click(|ctx, _data: &mut Vector<MergeCandidate>, _env| {
+                        ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                    })
+                    .padding(10.0),
+            ),
+    );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Merge with another book"))
+        .window_size((600., 500.))
+}
@@ -0,0 +1,109 @@
+use druid::im::HashMap;
+use druid::{Data, Selector};
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::persistence::write_atomically;
+use crate::{Fingerprint, PageNum};
+
+// ' then a letter on a page: jump to that global mark, or set it here if it isn't set yet.
+// Marks are stored under the capital letter, and a prefix keeps them clear of each book's own letters
+pub const GLOBAL_MARK_KEY: Selector<(String, Fingerprint, PageNum)> =
+    Selector::new("global-mark-key");
+// ' then Shift+letter: move the mark to this page even if it's already set
+pub const SET_GLOBAL_MARK: Selector<(String, Fingerprint, PageNum)> =
+    Selector::new("set-global-mark");
+// Space on a page clears global marks pointing at it, along with its own bookmark
+pub const CLEAR_GLOBAL_MARKS: Selector<(Fingerprint, PageNum)> =
+    Selector::new("clear-global-marks");
+
+// kept in the syncable directory next to the books' info files, so marks follow you between machines
+pub const GLOBAL_MARKS_FILE: &str = "global_marks.json";
+
+#[derive(Clone, Debug, Data, Serialize, Deserialize, PartialEq)]
+pub struct GlobalMark {
+    // empty once the mark has been cleared, kept so the clearing syncs rather than being undone by an older copy
+    pub fingerprint: Fingerprint,
+    pub page: PageNum,
+    // seconds since the epoch, the newest change to each letter wins when merging
+    pub changed_at: u64,
+}
+
+impl GlobalMark {
+    pub fn is_set(&self) -> bool {
+        !self.fingerprint.is_empty()
+    }
+}
+
+pub type GlobalMarks = HashMap<String, GlobalMark>;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn new_mark(fingerprint: &str, page: PageNum) -> GlobalMark {
+    GlobalMark {
+        fingerprint: fingerprint.to_string(),
+        page,
+        changed_at: now(),
+    }
+}
+
+pub fn cleared_mark() -> GlobalMark {
+    new_mark("", 0)
+}
+
+pub fn marks_path(syncable_dir: &str) -> PathBuf {
+    let mut path = PathBuf::from(syncable_dir);
+    path.push(GLOBAL_MARKS_FILE);
+    path
+}
+
+pub fn is_marks_file(path: &Path) -> bool {
+    path.file_name()
+        .map_or(false, |name| name == GLOBAL_MARKS_FILE)
+}
+
+pub fn load_marks(path: &Path) -> GlobalMarks {
+    match fs::read_to_string(path) {
+        Ok(serialized) => match serde_json::from_str(&serialized) {
+            Ok(marks) => marks,
+            Err(e) => {
+                println!("Error reading global marks {:?}: {}", path, e);
+                GlobalMarks::new()
+            }
+        },
+        Err(_) => GlobalMarks::new(),
+    }
+}
+
+pub fn merge_marks(ours: &GlobalMarks, theirs: &GlobalMarks) -> GlobalMarks {
+    let mut merged = ours.clone();
+    for (letter, mark) in theirs {
+        match ours.get(letter) {
+            Some(our_mark) if our_mark.changed_at >= mark.changed_at => (),
+            _ => {
+                merged.insert(letter.clone(), mark.clone());
+            }
+        }
+    }
+    merged
+}
+
+// folds in whatever another machine wrote since we last read the file, then writes the result
+// returns the merged marks
+pub fn save_marks(path: &Path, marks: &GlobalMarks) -> GlobalMarks {
+    let merged = merge_marks(marks, &load_marks(path));
+    let serialized = serde_json::to_string(&merged).unwrap();
+    if let Err(e) = write_atomically(path, &serialized[..]) {
+        println!("Error writing file: {}", e);
+    }
+    merged
+}
@@ -0,0 +1,182 @@
+use druid::im::Vector;
+use druid::{Data, Point, Rect};
+
+use mupdf::{Page, TextPageOptions};
+
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+
+// tag colour given to highlights made on pages with no tags
+pub const DEFAULT_HIGHLIGHT_COLOR: usize = 4; // yellow
+
+#[derive(Clone, Debug, Data, Serialize, Deserialize, PartialEq)]
+pub struct Highlight {
+    // one rectangle per line of selected text, in the same page-normalised coordinates as crop margins
+    pub quads: Vector<Rect>,
+    pub text: String,
+    // drawn in the colour of this tag number
+    pub color: usize,
+}
+
+impl Highlight {
+    pub fn contains(&self, p: Point) -> bool {
+        self.quads.iter().any(|r| r.contains(p))
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let mut quads = self.quads.iter();
+        let first = quads.next().copied().unwrap_or(Rect::ZERO);
+        quads.fold(first, |acc, r| acc.union(*r))
+    }
+}
+
+pub struct PageChar {
+    pub ch: char,
+    pub rect: Rect,
+    pub line: usize,
+}
+
+// every character on the page in reading order, fetched once when a selection starts rather than on each mouse move
+pub fn page_chars(page: &Page) -> Vec<PageChar> {
+    let mut chars = Vec::<PageChar>::new();
+
+    let size = match page.bounds() {
+        Ok(size) => size,
+        Err(e) => {
+            println!("Unable to get page bounds: {}", e);
+            return chars;
+        }
+    };
+    let w = size.width() as f64;
+    let h = size.height() as f64;
+
+    let text_page = match page.to_text_page(TextPageOptions::empty()) {
+        Ok(text_page) => text_page,
+        Err(e) => {
+            println!("Unable to extract page text: {}", e);
+            return chars;
+        }
+    };
+
+    let mut line_number = 0;
+    for block in text_page.blocks() {
+        for line in block.lines() {
+            for c in line.chars() {
+                if let Some(ch) = c.char() {
+                    let q = c.quad();
+                    chars.push(PageChar {
+                        ch,
+                        rect: Rect::new(
+                            q.ul.x as f64 / w,
+                            q.ul.y as f64 / h,
+                            q.lr.x as f64 / w,
+                            q.lr.y as f64 / h,
+                        ),
+                        line: line_number,
+                    });
+                }
+            }
+            line_number += 1;
+        }
+    }
+    chars
+}
+
+fn nearest_char(chars: &[PageChar], p: Point) -> Option<usize> {
+    let distance = |c: &PageChar| (c.rect.center() - p).hypot();
+    chars
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            distance(a)
+                .partial_cmp(&distance(b))
+                .unwrap_or(Ordering::Equal)
+        })
+        .map(|(idx, _)| idx)
+}
+
+// the run of text between the characters nearest where the drag started and where it is now
+pub fn select_between(
+    chars: &[PageChar],
+    start: Point,
+    end: Point,
+    color: usize,
+) -> Option<Highlight> {
+    let a = nearest_char(chars, start)?;
+    let b = nearest_char(chars, end)?;
+    let (first, last) = (usize::min(a, b), usize::max(a, b));
+
+    let mut quads = Vector::<Rect>::new();
+    let mut text = String::new();
+
+    let mut line = chars[first].line;
+    let mut line_rect = chars[first].rect;
+    for c in &chars[first..=last] {
+        if c.line != line {
+            quads.push_back(line_rect);
+            line = c.line;
+            line_rect = c.rect;
+            if !text.ends_with(' ') {
+                text.push(' ');
+            }
+        } else {
+            line_rect = line_rect.union(c.rect);
+        }
+        text.push(c.ch);
+    }
+    quads.push_back(line_rect);
+
+    Some(Highlight {
+        quads,
+        text: text.trim().to_string(),
+        color,
+    })
+}
+
+// the text under a highlight that wasn't made by dragging, e.g. one imported from the PDF file
+pub fn text_within(chars: &[PageChar], quads: &Vector<Rect>) -> String {
+    let mut text = String::new();
+    let mut line = None;
+    for c in chars {
+        if quads.iter().any(|q| q.contains(c.rect.center())) {
+            if line.is_some() && line != Some(c.line) && !text.ends_with(' ') {
+                text.push(' ');
+            }
+            line = Some(c.line);
+            text.push(c.ch);
+        }
+    }
+    text.trim().to_string()
+}
+
+// up to `context` characters either side of the text inside `rect`, on one line, for showing a search hit
+pub fn text_around(chars: &[PageChar], rect: Rect, context: usize) -> String {
+    let inside: Vec<usize> = chars
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| rect.contains(c.rect.center()))
+        .map(|(idx, _)| idx)
+        .collect();
+    let (first, last) = match (inside.first(), inside.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return String::new(),
+    };
+    let start = first.saturating_sub(context);
+    let end = usize::min(chars.len() - 1, last + context);
+
+    let mut text = String::new();
+    if start > 0 {
+        text.push('…');
+    }
+    for (idx, c) in chars[start..=end].iter().enumerate() {
+        if idx > 0 && c.line != chars[start + idx - 1].line && !text.ends_with(' ') {
+            text.push(' ');
+        }
+        text.push(c.ch);
+    }
+    if end < chars.len() - 1 {
+        text.push('…');
+    }
+    text.trim().to_string()
+}
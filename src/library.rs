@@ -0,0 +1,183 @@
+use druid::{ExtEventSink, Selector, Target};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::canonical_path_string;
+use crate::fingerprint::{identify, FileIdentity};
+
+// looks through the library folders in the preferences for books not seen before
+pub const SCAN_LIBRARY: Selector = Selector::new("scan-library");
+// from the filesystem watcher, a PDF in one of the library folders was added or changed, and has since stopped changing
+pub const LIBRARY_FILE_CHANGED: Selector<PathBuf> = Selector::new("library-file-changed");
+// from the scanning thread, with the books it found
+pub const LIBRARY_SCANNED: Selector<Vec<ScannedPdf>> = Selector::new("library-scanned");
+
+// copying a big PDF in produces a stream of modify events, the file is only scanned once they've stopped for this long
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+pub struct ScannedPdf {
+    pub path: String,
+    pub identity: FileIdentity,
+    pub title: String,
+    pub author: String,
+}
+
+// the preferences keep the folders as text, one per line, "~" is the home directory
+pub fn library_folders(folders: &str) -> Vec<PathBuf> {
+    let home = directories::UserDirs::new().map(|user_dirs| user_dirs.home_dir().to_path_buf());
+    folders
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| match (line.strip_prefix("~"), &home) {
+            (Some(rest), Some(home)) => {
+                home.join(rest.trim_start_matches(|c| c == '/' || c == '\\'))
+            }
+            _ => PathBuf::from(line),
+        })
+        .collect()
+}
+
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.to_string_lossy().to_lowercase() == "pdf")
+}
+
+pub fn find_pdfs(dir: &Path, found: &mut Vec<PathBuf>) {
+    find_pdfs_in(dir, found, &mut HashSet::new());
+}
+
+// symlinked folders are followed, but each folder is only searched once so a link back up the tree can't loop forever
+fn find_pdfs_in(dir: &Path, found: &mut Vec<PathBuf>, visited: &mut HashSet<PathBuf>) {
+    match fs::canonicalize(dir) {
+        Ok(canonical) => {
+            if !visited.insert(canonical) {
+                return;
+            }
+        }
+        Err(e) => {
+            println!("Error reading library folder {:?}: {}", dir, e);
+            return;
+        }
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Error reading library folder {:?}: {}", dir, e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // skips things like .git and .Trash
+        let hidden = path
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            find_pdfs_in(&path, found, visited);
+        } else if is_pdf(&path) {
+            found.push(path);
+        }
+    }
+}
+
+fn scan_file(path: &Path, known_paths: &HashSet<String>) -> Option<ScannedPdf> {
+    let path_string = canonical_path_string(path)?;
+    if known_paths.contains(&path_string) {
+        return None;
+    }
+
+    match mupdf::pdf::PdfDocument::open(&path_string) {
+        Ok(pdf_doc) => Some(ScannedPdf {
+            identity: identify(&pdf_doc, &path_string),
+            title: pdf_doc
+                .metadata(mupdf::document::MetadataName::Title)
+                .unwrap_or_default(),
+            author: pdf_doc
+                .metadata(mupdf::document::MetadataName::Author)
+                .unwrap_or_default(),
+            path: path_string,
+        }),
+        Err(e) => {
+            println!("Error opening PDF file {:?} while scanning: {}", path, e);
+            None
+        }
+    }
+}
+
+// opening every PDF in a big library takes a while, so it's done away from the UI thread
+// `paths` can be folders, searched recursively, or single files; ones already in `known_paths` aren't opened again
+pub fn start_scan(sink: ExtEventSink, paths: Vec<PathBuf>, known_paths: HashSet<String>) {
+    std::thread::spawn(move || {
+        let mut pdfs = Vec::<PathBuf>::new();
+        // shared between the folders, in case one is inside another
+        let mut visited = HashSet::new();
+        for path in &paths {
+            if path.is_dir() {
+                find_pdfs_in(path, &mut pdfs, &mut visited);
+            } else if is_pdf(path) {
+                pdfs.push(path.clone());
+            }
+        }
+
+        let scanned: Vec<ScannedPdf> = pdfs
+            .iter()
+            .filter_map(|path| scan_file(path, &known_paths))
+            .collect();
+        if scanned.is_empty() {
+            return;
+        }
+        println!("Found {} new books in the library folders", scanned.len());
+        if let Err(e) = sink.submit_command(LIBRARY_SCANNED, Box::new(scanned), Target::Auto) {
+            println!("error sending library scan results: {}", e);
+        }
+    });
+}
+
+// the filesystem watcher sends changed PDFs here, and LIBRARY_FILE_CHANGED is sent for each once its writes have settled
+pub fn start_change_debouncer(sink: ExtEventSink) -> Sender<PathBuf> {
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    std::thread::spawn(move || {
+        // when each path was last seen changing
+        let mut pending = HashMap::<PathBuf, Instant>::new();
+        loop {
+            let received = match pending.values().min() {
+                Some(oldest) => receiver.recv_timeout(
+                    (*oldest + SETTLE_TIME).saturating_duration_since(Instant::now()),
+                ),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(path) => {
+                    pending.insert(path, Instant::now());
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            let now = Instant::now();
+            let settled: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, changed)| now.duration_since(**changed) >= SETTLE_TIME)
+                .map(|(path, _)| path.clone())
+                .collect();
+            for path in settled {
+                pending.remove(&path);
+                if let Err(e) =
+                    sink.submit_command(LIBRARY_FILE_CHANGED, Box::new(path), Target::Auto)
+                {
+                    println!("error sending file change notification: {}", e);
+                }
+            }
+        }
+    });
+    sender
+}
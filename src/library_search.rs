@@ -0,0 +1,365 @@
+use druid::im::{HashMap, Vector};
+use druid::keyboard_types::Key;
+use druid::widget::prelude::*;
+use druid::widget::{
+    Button, Controller, CrossAxisAlignment, Flex, Label, LineBreaking, List, Scroll, TextBox,
+};
+use druid::{
+    commands as sys_cmds, ExtEventSink, Lens, LocalizedString, Rect, Selector, Target, WidgetExt,
+    WindowDesc,
+};
+
+use mupdf::pdf::PdfDocument;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::export::page_label;
+use crate::highlights::{page_chars, text_around};
+use crate::pdf_view::{make_pdf_view_window, PdfViewState};
+use crate::{AppState, DocumentInfo, Fingerprint, PageNum};
+
+// opens the window for searching the text of every book, or brings it forward
+pub const SHOW_LIBRARY_SEARCH: Selector = Selector::new("show-library-search");
+// searches for whatever's in the window's text box
+pub const START_LIBRARY_SEARCH: Selector = Selector::new("start-library-search");
+// from the search thread, after each book
+pub const LIBRARY_SEARCH_PROGRESS: Selector<SearchProgress> =
+    Selector::new("library-search-progress");
+// opens the book in a new window at the hit's page
+pub const OPEN_LIBRARY_HIT: Selector<LibraryHit> = Selector::new("open-library-hit");
+
+const MAX_HITS_PER_PAGE: u32 = 100;
+// a common word would otherwise list most of the library's pages
+const MAX_HITS_PER_BOOK: usize = 200;
+// characters of text shown either side of each hit
+const SNIPPET_CONTEXT: usize = 40;
+
+// bumped by each new search, so one still running on its thread knows to give up
+static CURRENT_SEARCH: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct LibraryHit {
+    pub fingerprint: Fingerprint,
+    pub page: PageNum,
+    // in page-normalised coordinates, like the find bar's results
+    pub rect: Rect,
+    snippet: String,
+    page_label: String,
+}
+
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct BookHits {
+    description: String,
+    hits: Vector<LibraryHit>,
+    // stopped at MAX_HITS_PER_BOOK
+    truncated: bool,
+}
+
+#[derive(Clone, Default, Data, Lens, PartialEq)]
+pub struct LibrarySearch {
+    pub query: String,
+    // the query the results are for, the text box may have been changed since
+    pub searched_for: String,
+    results: Vector<BookHits>,
+    status: String,
+}
+
+pub struct SearchProgress {
+    search: usize,
+    books_searched: usize,
+    books_total: usize,
+    hits: Option<BookHits>,
+}
+
+// a book is opened from the first of its paths that works, as when opening it from the book list
+pub struct BookToSearch {
+    pub fingerprint: Fingerprint,
+    pub description: String,
+    pub paths: Vec<String>,
+}
+
+impl LibrarySearch {
+    pub fn start(&mut self, query: &str, books_total: usize) {
+        self.searched_for = query.to_string();
+        self.results.clear();
+        self.status = if query.is_empty() {
+            String::new()
+        } else if books_total == 0 {
+            "There are no books on this machine to search yet.".to_string()
+        } else {
+            format!("Searching {} books...", books_total)
+        };
+    }
+
+    fn hit_count(&self) -> usize {
+        self.results.iter().map(|book| book.hits.len()).sum()
+    }
+
+    pub fn add_progress(
+        &mut self,
+        progress: &SearchProgress,
+        all_info: &HashMap<Fingerprint, DocumentInfo>,
+    ) {
+        // results from a search since replaced by another
+        if progress.search != CURRENT_SEARCH.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(book) = &progress.hits {
+            let mut book = book.clone();
+            for hit in book.hits.iter_mut() {
+                if let Some(info) = all_info.get(&hit.fingerprint) {
+                    hit.page_label = page_label(info, hit.page);
+                }
+            }
+            self.results.push_back(book);
+        }
+
+        let hits = match self.hit_count() {
+            1 => "1 hit".to_string(),
+            n => format!("{} hits", n),
+        };
+        self.status = if progress.books_searched < progress.books_total {
+            format!(
+                "Searched {} of {} books, {} so far...",
+                progress.books_searched, progress.books_total, hits
+            )
+        } else {
+            format!(
+                "{} in {} of {} books.",
+                hits,
+                self.results.len(),
+                progress.books_total
+            )
+        };
+    }
+}
+
+pub fn cancel_search() {
+    CURRENT_SEARCH.fetch_add(1, Ordering::SeqCst);
+}
+
+fn still_wanted(search: usize) -> bool {
+    CURRENT_SEARCH.load(Ordering::SeqCst) == search
+}
+
+fn search_book(book: &BookToSearch, query: &str, search: usize) -> Option<BookHits> {
+    let pdf_doc = book
+        .paths
+        .iter()
+        .find_map(|path| PdfDocument::open(path).ok())?;
+    let page_count = pdf_doc.page_count().unwrap_or(0);
+
+    let mut hits = Vector::<LibraryHit>::new();
+    let mut truncated = false;
+    'pages: for page_num in 0..page_count {
+        if !still_wanted(search) {
+            return None;
+        }
+        let page = match pdf_doc.load_page(page_num) {
+            Ok(page) => page,
+            Err(_) => continue,
+        };
+        let quads = match page.search(query, MAX_HITS_PER_PAGE) {
+            Ok(quads) if !quads.is_empty() => quads,
+            _ => continue,
+        };
+        let size = match page.bounds() {
+            Ok(size) => size,
+            Err(_) => continue,
+        };
+        let w = size.width() as f64;
+        let h = size.height() as f64;
+
+        let chars = page_chars(&page);
+        for q in quads {
+            if hits.len() == MAX_HITS_PER_BOOK {
+                truncated = true;
+                break 'pages;
+            }
+            let rect = Rect::new(
+                q.ul.x as f64 / w,
+                q.ul.y as f64 / h,
+                q.lr.x as f64 / w,
+                q.lr.y as f64 / h,
+            );
+            hits.push_back(LibraryHit {
+                fingerprint: book.fingerprint.clone(),
+                page: page_num as PageNum,
+                rect,
+                snippet: text_around(&chars, rect, SNIPPET_CONTEXT),
+                // filled in back on the UI thread, which has the books' page offsets
+                page_label: String::new(),
+            });
+        }
+    }
+
+    if hits.is_empty() {
+        None
+    } else {
+        Some(BookHits {
+            description: book.description.clone(),
+            hits,
+            truncated,
+        })
+    }
+}
+
+// opening and searching every page of every book takes a while, so it's done away from the UI thread
+// starting a search stops any earlier one
+pub fn start_search(sink: ExtEventSink, query: String, books: Vec<BookToSearch>) {
+    cancel_search();
+    if query.is_empty() {
+        return;
+    }
+    let search = CURRENT_SEARCH.load(Ordering::SeqCst);
+
+    std::thread::spawn(move || {
+        let books_total = books.len();
+        for (idx, book) in books.iter().enumerate() {
+            let hits = search_book(book, &query, search);
+            if !still_wanted(search) {
+                return;
+            }
+            let progress = SearchProgress {
+                search,
+                books_searched: idx + 1,
+                books_total,
+                hits,
+            };
+            if let Err(e) =
+                sink.submit_command(LIBRARY_SEARCH_PROGRESS, Box::new(progress), Target::Auto)
+            {
+                println!("error sending library search results: {}", e);
+                return;
+            }
+        }
+    });
+}
+
+// a new view of the book at the hit's page, with the hit outlined as the find bar would
+pub fn make_hit_window(
+    data: &mut AppState,
+    doc_idx: usize,
+    hit: &LibraryHit,
+) -> WindowDesc<AppState> {
+    if let Some(info) = data.all_local_documents_info.get_mut(&hit.fingerprint) {
+        info.most_recent_page = hit.page;
+    }
+    let info = data.all_local_documents_info[&hit.fingerprint].clone();
+
+    let mut view = PdfViewState::new(
+        doc_idx,
+        data.loaded_documents[doc_idx].clone(),
+        info,
+        data.preferences.clone(),
+    );
+    view.find_goal = data.library_search.searched_for.clone();
+    view.search_results
+        .borrow_mut()
+        .insert(hit.page, vec![hit.rect]);
+    view.search_progress = Some((hit.page, hit.page));
+
+    make_pdf_view_window(data, doc_idx, Some(view))
+}
+
+// Enter searches, Escape closes the window
+struct SearchBoxController;
+
+impl<W: Widget<String>> Controller<String, W> for SearchBoxController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut String,
+        env: &Env,
+    ) {
+        match event {
+            Event::WindowConnected => {
+                ctx.request_focus();
+                child.event(ctx, event, data, env)
+            }
+            Event::KeyDown(e) if e.key == Key::Enter => {
+                ctx.submit_command(START_LIBRARY_SEARCH);
+                ctx.set_handled();
+            }
+            Event::KeyDown(e) if e.key == Key::Escape => {
+                ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+fn make_hit_row() -> impl Widget<LibraryHit> {
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(|hit: &LibraryHit, _env: &_| hit.page_label.clone()).fix_width(100.))
+        .with_flex_child(
+            Label::new(|hit: &LibraryHit, _env: &_| hit.snippet.clone())
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+            1.,
+        )
+        .padding((20., 2., 10., 2.))
+        .on_click(|ctx, hit: &mut LibraryHit, _env| {
+            ctx.submit_command(OPEN_LIBRARY_HIT.with(hit.clone()))
+        })
+}
+
+fn make_book_hits() -> impl Widget<BookHits> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(|book: &BookHits, _env: &_| {
+                format!(
+                    "{} ({}{} hits)",
+                    book.description,
+                    if book.truncated { "first " } else { "" },
+                    book.hits.len()
+                )
+            })
+            .with_text_size(18.)
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding((10., 10., 10., 4.)),
+        )
+        .with_child(List::new(make_hit_row).lens(BookHits::hits))
+}
+
+pub fn make_library_search_window() -> WindowDesc<AppState> {
+    let ui = Flex::column()
+        .with_child(
+            Flex::row()
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder("words to look for in every book")
+                        .controller(SearchBoxController)
+                        .expand_width()
+                        .lens(LibrarySearch::query),
+                    1.,
+                )
+                .with_default_spacer()
+                .with_child(Button::new("Search").on_click(
+                    |ctx, _data: &mut LibrarySearch, _env| ctx.submit_command(START_LIBRARY_SEARCH),
+                ))
+                .padding(10.),
+        )
+        .with_child(
+            Label::new(|search: &LibrarySearch, _env: &_| search.status.clone())
+                .padding((10., 0.))
+                .align_left(),
+        )
+        .with_flex_child(
+            Scroll::new(List::new(make_book_hits).lens(LibrarySearch::results))
+                .vertical()
+                .expand(),
+            1.,
+        )
+        .lens(AppState::library_search);
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Search every book"))
+        .window_size((700., 800.))
+}
@@ -30,6 +30,7 @@ use druid::{
     Color,
     Command,
     DelegateCtx,
+    ExtEventSink,
     FileInfo,
     FontDescriptor,
     FontStyle,
@@ -74,7 +75,7 @@ use mupdf::{Colorspace, Matrix, Page};
 
 use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
@@ -103,6 +104,76 @@ use preferences::{make_preferences_window, Preferences};
 mod contents_tree;
 mod find_goto_controllers;
 
+mod sync_merge;
+use sync_merge::{SyncConflict, RESOLVE_SYNC_CONFLICT};
+
+mod doc_info_schema;
+use doc_info_schema::{parse_document_info, SchemaError, CURRENT_SCHEMA_VERSION};
+
+mod warnings_window;
+
+mod persistence;
+use persistence::write_atomically;
+
+mod highlights;
+use highlights::{Highlight, DEFAULT_HIGHLIGHT_COLOR};
+
+mod notes;
+use notes::{Note, NoteEdit, EDIT_NOTE, SAVE_NOTE};
+
+mod backups_window;
+
+mod export;
+
+mod pdf_annotations;
+use pdf_annotations::{ImportedAnnotations, IMPORT_PDF_ANNOTATIONS, PDF_ANNOTATIONS_IMPORTED};
+
+mod annotated_pdf;
+
+mod tag_table;
+
+mod tag_query;
+
+mod bookmarks;
+
+mod reading_time;
+
+mod review;
+mod library;
+use library::{LIBRARY_FILE_CHANGED, LIBRARY_SCANNED, SCAN_LIBRARY};
+mod library_search;
+mod text_index;
+mod search_worker;
+mod search_results;
+use search_results::chapter_titles;
+mod fingerprint;
+use fingerprint::{
+    FileIdentity, FingerprintAliases, FingerprintCollision, CHOOSE_BOOK_TO_MERGE, MERGE_BOOKS,
+    RESOLVE_FINGERPRINT_COLLISION,
+};
+mod text_match;
+use text_index::{TextIndex, INDEX_DOCUMENT, TEXT_INDEX_READY};
+use library_search::{
+    BookToSearch, LibrarySearch, LIBRARY_SEARCH_PROGRESS, OPEN_LIBRARY_HIT, SHOW_LIBRARY_SEARCH,
+    START_LIBRARY_SEARCH,
+};
+
+mod prerequisites;
+use prerequisites::{Prerequisite, EDIT_PREREQUISITES, SAVE_PREREQUISITES, SHOW_READING_ORDER};
+mod relocate;
+use relocate::{MissingBook, WantedBook, BOOK_RELOCATED, LOCATED_BOOK_FILE, LOCATE_BOOK};
+
+use review::{ReviewItem, ReviewState, GRADE_REVIEW, REVIEW_DUE_PAGES, SKIP_REVIEW};
+use bookmarks::{ADD_NAMED_BOOKMARK, GO_TO_BOOKMARK, SAVE_NAMED_BOOKMARK};
+
+mod global_marks;
+use global_marks::{
+    GlobalMark, GlobalMarks, CLEAR_GLOBAL_MARKS, GLOBAL_MARK_KEY, SET_GLOBAL_MARK,
+};
+use tag_table::{TagDef, TagRow, EDIT_TAG_TABLE, SAVE_TAG_TABLE};
+
+use backups_window::{RESTORE_BACKUP, SHOW_BACKUPS};
+
 type PageNum = usize; // mupdf-rs uses i32, i'm not sure why it's signed
 
 const UNIT_SQUARE: Rect = Rect {
@@ -118,32 +189,52 @@ enum CropMargins {
     DistinguishEvenAndOddPages(Rect, Rect),
 }
 
-// fingerprinting PDFs https://www.seanh.cc/2017/11/22/pdf-fingerprinting/
-fn fingerprint(document: &PdfDocument) -> Option<String> {
-    let trailer = document.trailer().unwrap();
-    let pdf_id = trailer.get_dict("ID").expect("unwrap 1 failed");
-println!("fingerprinder");
-    match pdf_id {
-        Some(pdf_id) => {
+fn canonical_path_string(path: &Path) -> Option<String> {
+    let canon = path.canonicalize().ok()?;
+    let mut path_string: String = canon.to_str()?.to_string();
 
-            let fingerprint = pdf_id
-                .resolve()
-                .unwrap()
-                .unwrap()
-                .get_array(0)
-                .unwrap()
-                .unwrap()
-                .to_string();
+    //        #[cfg(target_os = "windows")]
+    const VERBATIM_PREFIX: &str = r#"\\?\"#;
+    if path_string.starts_with(VERBATIM_PREFIX) {
+        path_string = path_string[VERBATIM_PREFIX.len()..].to_string();
+    }
+    Some(path_string)
+}
 
-            let fngr_string = fingerprint[1..fingerprint.len() - 1].to_string();
-            if fngr_string == "67C6697351FF4AEC29CDBAABF2FBE346".to_string() {
-                println!("oh noes!");
-                return None
+// paths in the home directory start with "~"
+fn user_facing_path(path_string: &str) -> String {
+    let mut user_facing_path = path_string.to_string();
+
+    if let Some(user_dirs) = directories::UserDirs::new() {
+        let home = user_dirs.home_dir().to_str().unwrap();
+        if path_string.starts_with(home) {
+            user_facing_path = "~".to_string();
+            let mut first = true;
+            for s in path_string.split(home).skip(1) {
+                if !first {
+                    user_facing_path.push_str(home);
+                }
+                user_facing_path.push_str(s);
+                first = false;
             }
-            Some(fingerprint[1..fingerprint.len() - 1].to_string())
         }
-        None => None,
     }
+    user_facing_path
+}
+
+// "Title by Author", from the PDF's metadata, or the file's path if it has no title
+fn describe_book(title: &str, authors: &str, user_facing_path: &str) -> String {
+    let mut description = title.to_string();
+
+    if description.is_empty() {
+        description.push_str(user_facing_path);
+    }
+
+    if !authors.is_empty() {
+        description.push_str(" by ");
+        description.push_str(authors);
+    }
+    description
 }
 
 use crate::pdf_text_widget::lerp_rect;
@@ -171,18 +262,38 @@ pub struct DocumentInfo {
     page_count: PageNum,
     #[serde(default)]
     page_offset: i32,
-    // #[serde(default)]
-    // prerequistes: Vector<Fingerprint>,
-    // #[serde(default)]
-    // requisite_for: Vector<Fingerprint>,
+    // books to read before this one, which books need this one is worked out from theirs, see prerequisites.rs
+    #[serde(default)]
+    prerequisites: HashMap<Fingerprint, Prerequisite>,
     #[serde(default)]
     description: String,
+    #[serde(default)]
+    highlights: HashMap<PageNum, Vector<Highlight>>,
+    #[serde(default)]
+    notes: HashMap<PageNum, Vector<Note>>,
+    // highlights and comments already in the PDF file are copied in the first time it's opened
+    #[serde(default)]
+    pdf_annotations_imported: bool,
+    // tag names and colours this book doesn't share with the defaults in the preferences, by tag number
+    #[serde(default)]
+    tag_table: HashMap<usize, TagDef>,
+    // seconds spent with each page on screen, and in total on each day ("YYYY-MM-DD"), see reading_time.rs
+    #[serde(default)]
+    reading_time: HashMap<PageNum, u64>,
+    #[serde(default)]
+    daily_reading_time: HashMap<String, u64>,
+    // spaced repetition schedule of the pages carrying the review tag from the preferences, see review.rs
+    #[serde(default)]
+    reviews: HashMap<PageNum, ReviewState>,
+    #[serde(default)]
+    schema_version: u32,
 }
 
 use CropMargins::{AllPagesSame, DistinguishEvenAndOddPages};
 
 impl DocumentInfo {
-    pub fn from_fingerprint(data_dir: &PathBuf, fingerprint: &str) -> Self {
+    // also returns a warning for the user if the stored info couldn't be used
+    pub fn from_fingerprint(data_dir: &PathBuf, fingerprint: &str) -> (Self, Option<String>) {
         //        if let Some(dir) = data_dir {
         let mut path: PathBuf = data_dir.clone();
         path.push(&fingerprint);
@@ -192,14 +303,37 @@ impl DocumentInfo {
         //                 Ok(metadata) => metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
         //                 Err(_) => SystemTime::UNIX_EPOCH};
 
-        match fs::read_to_string(&path) {
-            Ok(serialized) => match serde_json::from_str(&serialized) {
-                Ok(loaded_info) => return loaded_info,
-                Err(e) => println!("error loading doc info: {}", e),
+        let warning = match fs::read_to_string(&path) {
+            Ok(serialized) => match parse_document_info(&serialized) {
+                Ok(loaded_info) => return (loaded_info, None),
+                Err(SchemaError::TooNew(version)) => {
+                    // leave it alone, saving refuses to overwrite it too
+                    Some(format!(
+                        "{:?} was written by a newer version of PDF Student (format {}), so this book's tags and bookmarks can't be shown or changed here.",
+                        path, version
+                    ))
+                }
+                Err(e) => {
+                    println!("error loading doc info: {}", e);
+                    let aside = doc_info_schema::set_aside_unreadable(&path);
+                    Some(format!(
+                        "{:?} couldn't be read ({}), so it has been kept as {:?} and this book starts with blank tags and bookmarks.",
+                        path,
+                        e,
+                        aside.unwrap_or_else(|| path.clone())
+                    ))
+                }
             },
-            Err(e) => println!("doc info loading error: {} {:?}", e, path),
-        }
+            Err(e) => {
+                println!("doc info loading error: {} {:?}", e, path);
+                None
+            }
+        };
+
+        (DocumentInfo::blank(fingerprint), warning)
+    }
 
+    pub fn blank(fingerprint: &str) -> Self {
         DocumentInfo {
             default_margins: AllPagesSame(Rect::new(0.05, 0.05, 0.95, 0.95)),
             custom_margins: HashMap::<PageNum, Rect>::new(),
@@ -216,8 +350,15 @@ impl DocumentInfo {
             page_offset: 0,
 
             description: String::new(),
-            // prerequistes: Vector::<Fingerprint>::new(),
-            // requisite_for: Vector::<Fingerprint>::new(),
+            highlights: HashMap::<PageNum, Vector<Highlight>>::new(),
+            notes: HashMap::<PageNum, Vector<Note>>::new(),
+            pdf_annotations_imported: false,
+            tag_table: HashMap::<usize, TagDef>::new(),
+            reading_time: HashMap::<PageNum, u64>::new(),
+            daily_reading_time: HashMap::<String, u64>::new(),
+            reviews: HashMap::<PageNum, ReviewState>::new(),
+            prerequisites: HashMap::<Fingerprint, Prerequisite>::new(),
+            schema_version: CURRENT_SCHEMA_VERSION,
         }
     }
 
@@ -328,6 +469,14 @@ impl DocumentInfo {
         self.tags.insert(page, t ^ (1 << bit));
     }
 
+    // highlights take the colour of the page's lowest numbered tag
+    fn highlight_color(&self, page: PageNum) -> usize {
+        let bits = self.tag_bits(page);
+        (1..10)
+            .find(|bit| bits & (1 << bit) != 0)
+            .unwrap_or(DEFAULT_HIGHLIGHT_COLOR)
+    }
+
     pub fn add_bookmark(&mut self, c: &str, page: usize) {
         self.bookmarks.insert(c.to_string(), page);
     }
@@ -354,6 +503,10 @@ pub struct Document {
     reverse_bookmarks: HashMap<usize, String>,
     hyperlinks: HashMap<usize, Option<Vector<Hyperlink>>>,
     rcurrent_page_number_in_window_id: Arc<RefCell<HashMap<WindowId, PageNum>>>,
+    // arrives from another thread a little after the book's opened, searches go page by page until then
+    text_index: Option<Arc<TextIndex>>,
+    // where each entry in the table of contents starts, for naming the chapter search hits are in
+    chapters: Arc<Vec<(PageNum, String)>>,
 
     doc_info_changed: bool,
 }
@@ -367,6 +520,7 @@ impl Document {
         user_facing_path: String,
         rcurrent_page: &Arc<RefCell<HashMap<WindowId, PageNum>>>,
     ) -> Self {
+        let chapters = Arc::new(chapter_titles(&pdf_doc));
         let mut doc = Document {
             fingerprint: info.fingerprint.clone(),
             pdf_file: Arc::new(pdf_doc),
@@ -376,6 +530,8 @@ impl Document {
             reverse_bookmarks: HashMap::<usize, String>::new(),
             hyperlinks: HashMap::<usize, Option<Vector<Hyperlink>>>::new(),
             rcurrent_page_number_in_window_id: (*rcurrent_page).clone(),
+            text_index: None,
+            chapters,
             doc_info_changed: false,
         };
         doc.generate_reverse_bookmarks(&info);
@@ -400,8 +556,11 @@ impl Document {
     pub fn generate_reverse_bookmarks(&mut self, info: &DocumentInfo) {
         self.reverse_bookmarks.clear();
 
-        for (chr, page) in &info.bookmarks {
-            self.reverse_bookmarks.insert(*page, (*chr).to_string());
+        // a page with several bookmarks shows its letter shortcut if it has one, otherwise its first name alphabetically
+        let mut names: Vec<(&String, &PageNum)> = info.bookmarks.iter().collect();
+        names.sort_by_key(|(name, _)| (!bookmarks::is_letter_shortcut(name), (*name).clone()));
+        for (name, page) in names.into_iter().rev() {
+            self.reverse_bookmarks.insert(*page, name.to_string());
         }
     }
 
@@ -420,6 +579,9 @@ use std::io::BufReader;
 pub struct RecentDocumentsWithLocations {
     pub fingerprints: Vector<Fingerprint>,
     pub locations: HashMap<Fingerprint, Vector<String>>,
+    // of the file each book was last opened from here, to rule out most PDFs quickly when looking for one that's moved
+    #[serde(default)]
+    pub file_sizes: HashMap<Fingerprint, u64>,
 }
 
 // impl RecentDocumentsWithLocations {
@@ -462,10 +624,21 @@ pub struct AppState {
     preferences: Preferences,
     rcurrent_page_number_in_window_id: Arc<RefCell<HashMap<WindowId, PageNum>>>,
 
-    filesystem_watcher: Option<Arc<RecommendedWatcher>>,
+    filesystem_watcher: Option<Arc<Mutex<RecommendedWatcher>>>,
     just_saved: HashSet<String>, // after saving doc info, ignore the next notification from the filesystem watcher about the file changing
 
     search_filter: String,
+
+    global_marks: GlobalMarks, // shift+letter marks, each pointing at a page of any book
+    review_queue: Vector<ReviewItem>, // pages still to be graded in the review window, the front one is on screen
+    pdf_view_windows: HashMap<WindowId, Fingerprint>, // which book each open view window shows, for jumping to a global mark
+    library_search: LibrarySearch, // the text searched for in every book, and what's been found so far
+
+    sync_conflicts: Vector<SyncConflict>, // entries changed differently here and on another machine, waiting for the user to pick one
+    fingerprint_aliases: FingerprintAliases, // the record each file is kept under, where that isn't the file's own fingerprint, see fingerprint.rs
+    fingerprint_collisions: Vector<FingerprintCollision>, // files that ran into another book's record, waiting for the user to say if they're the same book
+    missing_books: Vector<MissingBook>, // books that weren't anywhere they were looked for, waiting for the user to locate them
+    warnings: Vector<String>,
 }
 
 impl AppState {
@@ -516,17 +689,28 @@ impl AppState {
         }
 
         let mut all_local_documents_info = HashMap::<Fingerprint, DocumentInfo>::new();
+        let mut warnings = Vector::<String>::new();
 
         if let Some(documents_on_this_machine) = &doc_locs {
             let dir = preferences.syncable_data_directory.clone();
             let p = PathBuf::from(dir);
             for fp in documents_on_this_machine.fingerprints.iter() {
-                all_local_documents_info
-                    .insert(fp.clone(), DocumentInfo::from_fingerprint(&p, &fp));
+                let (info, warning) = DocumentInfo::from_fingerprint(&p, &fp);
+                all_local_documents_info.insert(fp.clone(), info);
+                if let Some(warning) = warning {
+                    warnings.push_back(warning);
+                }
             }
         }
 
-        Self {
+        let global_marks = global_marks::load_marks(&global_marks::marks_path(
+            &preferences.syncable_data_directory,
+        ));
+        let fingerprint_aliases = fingerprint::load_aliases(&fingerprint::aliases_path(
+            &preferences.syncable_data_directory,
+        ));
+
+        let state = Self {
             loaded_documents: Vector::<Document>::new(),
             local_data_directory, //:    Arc::new(PathBuf::from(local_data_dir)),
             syncable_data_directory, //: Arc::new(PathBuf::from( sync_data_dir)),
@@ -539,10 +723,132 @@ impl AppState {
             filesystem_watcher: None,
             just_saved: HashSet::<String>::new(),
             preferences,
+            global_marks,
+            fingerprint_aliases,
+            warnings,
             ..AppState::default()
+        };
+
+        // whatever was just read from the sync directory is what this machine last agreed with
+        for info in state.all_local_documents_info.values() {
+            state.store_sync_base(info);
+        }
+
+        state
+    }
+
+    // books found in the library folders are remembered like opened ones, but listed after them
+    pub fn register_scanned_books(&mut self, scanned: &[library::ScannedPdf]) {
+        for pdf in scanned {
+            let fingerprint = match self.record_fingerprint(&pdf.identity, &pdf.path) {
+                Ok(fingerprint) => fingerprint,
+                Err(_) => {
+                    // asked about when it's opened, rather than once for every scan
+                    println!("{} looks like another book, leaving it out", pdf.path);
+                    continue;
+                }
+            };
+            if !self.all_local_documents_info.contains_key(&fingerprint) {
+                // another machine may already know the book
+                let (mut doc_info, warning) = DocumentInfo::from_fingerprint(
+                    &PathBuf::from(&self.preferences.syncable_data_directory),
+                    &fingerprint,
+                );
+                if let Some(warning) = warning {
+                    self.warnings.push_back(warning);
+                }
+                self.store_sync_base(&doc_info);
+
+                let mut changed = false;
+                if doc_info.page_count == 0 {
+                    doc_info.page_count = pdf.identity.page_count;
+                    changed = true;
+                }
+                if doc_info.description.is_empty() {
+                    doc_info.description =
+                        describe_book(&pdf.title, &pdf.author, &user_facing_path(&pdf.path));
+                    changed = true;
+                }
+
+                self.all_local_documents_info
+                    .insert(fingerprint.clone(), doc_info);
+                if changed {
+                    self.save_document_info(&fingerprint);
+                }
+            }
+
+            let locations = self
+                .recent_document_locations
+                .locations
+                .entry(fingerprint.clone())
+                .or_insert_with(Vector::<String>::new);
+            if !locations.contains(&pdf.path) {
+                locations.push_back(pdf.path.clone());
+            }
+            if !self
+                .recent_document_locations
+                .fingerprints
+                .contains(&fingerprint)
+            {
+                self.recent_document_locations
+                    .fingerprints
+                    .push_front(fingerprint.clone());
+            }
+        }
+    }
+
+    // every path a book has been seen at, so scans don't open them again
+    // a std set rather than an im one, as it's handed to the scanning thread
+    fn known_pdf_paths(&self) -> std::collections::HashSet<String> {
+        self.recent_document_locations
+            .locations
+            .values()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    fn watch_library_folders(&self) {
+        if let Some(watcher) = &self.filesystem_watcher {
+            if let Ok(mut watcher) = watcher.lock() {
+                for folder in library::library_folders(&self.preferences.library_folders) {
+                    if let Err(e) = watcher.watch(&folder, RecursiveMode::Recursive) {
+                        println!("problem trying to watch library folder {:?}: {}", folder, e);
+                    }
+                }
+            }
         }
     }
 
+    fn scan_library(&self, sink: ExtEventSink) {
+        library::start_scan(
+            sink,
+            library::library_folders(&self.preferences.library_folders),
+            self.known_pdf_paths(),
+        );
+    }
+
+    // every book this machine knows where to find, in the order of their descriptions
+    fn search_library(&mut self, sink: ExtEventSink) {
+        let query = self.library_search.query.trim().to_string();
+        let mut books: Vec<BookToSearch> = self
+            .all_local_documents_info
+            .values()
+            .filter_map(|info| {
+                let paths = self.recent_document_locations.locations.get(&info.fingerprint)?;
+                Some(BookToSearch {
+                    fingerprint: info.fingerprint.clone(),
+                    description: info.description.clone(),
+                    paths: paths.iter().cloned().collect(),
+                })
+            })
+            .collect();
+        books.sort_by(|a, b| a.description.cmp(&b.description));
+
+        self.library_search.start(&query, books.len());
+        library_search::start_search(sink, query, books);
+    }
+
     fn already_loaded(&self, fingerprint: String) -> Option<usize> {
         for (i, doc) in self.loaded_documents.iter().enumerate() {
             if doc.fingerprint == fingerprint {
@@ -558,64 +864,33 @@ impl AppState {
             println!("file doesn't seem to exist: {}", path.display());
             return None;
         }
-        let canon = path
-            .canonicalize()
-            .expect("unable to canonicalize file path");
-
-
-        let mut path_string: String = canon
-            .to_str()
-            .expect("filepath could not be converted to string")
-            .to_string();
-        
-//        #[cfg(target_os = "windows")]
-        const VERBATIM_PREFIX: &str = r#"\\?\"#;
-        if path_string.starts_with(VERBATIM_PREFIX) {
-            path_string = path_string[VERBATIM_PREFIX.len()..].to_string();
-        }
-
-        let mut user_facing_path = path_string.clone();
-
-        if let Some(user_dirs) = directories::UserDirs::new() {
-            let home = user_dirs.home_dir().to_str().unwrap();
-            if path_string.starts_with(home) {
-                user_facing_path = "~".to_string();
-                let mut first = true;
-                for s in path_string.split(home).skip(1) {
-                    if !first {
-                        user_facing_path.push_str(home);
-                    }
-                    user_facing_path.push_str(s);
-                    first = false;
-                }
-            }
-        }
+        let path_string = canonical_path_string(path).expect("unable to canonicalize file path");
+        let user_facing_path = user_facing_path(&path_string);
 
         match mupdf::pdf::PdfDocument::open(&path_string) {
             Ok(pdf_doc) => {
-                let fingerprint = fingerprint(&pdf_doc).unwrap_or_else(|| {
-                    let mut file = File::open(&path_string).unwrap();
-
-                    let mut buf = [0; 1024];
-
-                    if let Err(e) = file.read_exact(&mut buf) {
-                        println!(
-                            "error reading PDF file to compute document fingerprint: {}",
-                            e
-                        );
+                let identity = fingerprint::identify(&pdf_doc, &path_string);
+                let fingerprint = match self.record_fingerprint(&identity, &path_string) {
+                    Ok(fingerprint) => fingerprint,
+                    Err(collision) => {
+                        // opened once the user has said which book it is
+                        self.fingerprint_collisions
+                            .retain(|c| !c.is_about(&collision));
+                        self.fingerprint_collisions.push_back(collision);
+                        return None;
                     }
-                    let digest = md5::compute(&mut buf);
-
-                    let res = format!("{:x}", digest);
-                    res
-                });
+                };
 
                 println!("finginrepring: {}", fingerprint);
 
-                let mut doc_info = DocumentInfo::from_fingerprint(
+                let (mut doc_info, warning) = DocumentInfo::from_fingerprint(
                     &PathBuf::from(&self.preferences.syncable_data_directory),
                     &fingerprint,
                 );
+                if let Some(warning) = warning {
+                    self.warnings.push_back(warning);
+                }
+                self.store_sync_base(&doc_info);
 
                 let mut changed = false;
 
@@ -628,25 +903,16 @@ impl AppState {
                 if doc_info.description.is_empty() {
                     // this is the first time we've seen this PDF
                     changed = true;
-                    let title = pdf_doc.metadata(mupdf::document::MetadataName::Title);
-                    let authors = pdf_doc.metadata(mupdf::document::MetadataName::Author);
+                    let title = pdf_doc
+                        .metadata(mupdf::document::MetadataName::Title)
+                        .unwrap_or_default();
+                    let authors = pdf_doc
+                        .metadata(mupdf::document::MetadataName::Author)
+                        .unwrap_or_default();
 
                     // let trailer = pdf_doc.trailer().unwrap();
                     //                    let pdf_id = trailer.get_dict("ID").expect("unwrap 1 failed");
-                    if let Ok(res) = title {
-                        doc_info.description.push_str(&res);
-                    }
-
-                    if doc_info.description.is_empty() {
-                        doc_info.description.push_str(user_facing_path.as_str());
-                    }
-
-                    if let Ok(res) = authors {
-                        if !res.is_empty() {
-                            doc_info.description.push_str(" by ");
-                            doc_info.description.push_str(&res);
-                        }
-                    }
+                    doc_info.description = describe_book(&title, &authors, &user_facing_path);
                 }
 
                 self.all_local_documents_info
@@ -669,6 +935,11 @@ impl AppState {
                     &mut self.recent_document_locations.fingerprints,
                     fingerprint.clone(),
                 );
+                if let Ok(metadata) = fs::metadata(&path_string) {
+                    self.recent_document_locations
+                        .file_sizes
+                        .insert(fingerprint.clone(), metadata.len());
+                }
 
                 match self.already_loaded(fingerprint) {
                     None => {
@@ -692,6 +963,222 @@ impl AppState {
         }
     }
 
+    fn has_record(&self, fingerprint: &str) -> bool {
+        let mut path = PathBuf::from(&self.preferences.syncable_data_directory);
+        path.push(fingerprint);
+        path.set_extension("json");
+        self.all_local_documents_info.contains_key(fingerprint) || path.exists()
+    }
+
+    // the fingerprint of the record a file's info is kept under
+    // books first seen before fingerprints covered the content are still under their PDF ID, which different books
+    // from the same tool can share, so a second file claiming one of those, or one with a different page count, is
+    // left for the user to say whether it's the same book
+    fn record_fingerprint(
+        &mut self,
+        identity: &FileIdentity,
+        path_string: &str,
+    ) -> Result<Fingerprint, FingerprintCollision> {
+        if let Some(record) = self.fingerprint_aliases.get(&identity.fingerprint) {
+            return Ok(record.clone());
+        }
+        if identity.legacy == identity.fingerprint
+            || self.has_record(&identity.fingerprint)
+            || !self.has_record(&identity.legacy)
+        {
+            return Ok(identity.fingerprint.clone());
+        }
+
+        let claimed = self
+            .fingerprint_aliases
+            .iter()
+            .any(|(file, record)| *record == identity.legacy && *file != identity.fingerprint);
+        let (description, page_count) = match self.all_local_documents_info.get(&identity.legacy) {
+            Some(info) => (info.description.clone(), info.page_count),
+            None => {
+                let (info, _) = DocumentInfo::from_fingerprint(
+                    &PathBuf::from(&self.preferences.syncable_data_directory),
+                    &identity.legacy,
+                );
+                (info.description, info.page_count)
+            }
+        };
+
+        if claimed || (page_count != 0 && page_count != identity.page_count) {
+            Err(FingerprintCollision {
+                path: path_string.to_string(),
+                fingerprint: identity.fingerprint.clone(),
+                record: identity.legacy.clone(),
+                summary: format!(
+                    "{} ({} pages)\nlooks like \"{}\" ({} pages)",
+                    user_facing_path(path_string),
+                    identity.page_count,
+                    description,
+                    page_count
+                ),
+            })
+        } else {
+            self.fingerprint_aliases
+                .insert(identity.fingerprint.clone(), identity.legacy.clone());
+            self.save_fingerprint_aliases();
+            Ok(identity.legacy.clone())
+        }
+    }
+
+    fn save_fingerprint_aliases(&mut self) {
+        let path = fingerprint::aliases_path(&self.preferences.syncable_data_directory);
+        self.fingerprint_aliases = fingerprint::save_aliases(&path, &self.fingerprint_aliases);
+        // so the filesystem watcher's notification about it is ignored
+        if let Some(s) = path.to_str() {
+            self.just_saved.insert(s.to_string());
+        }
+    }
+
+    // the same book is filed under the record it ran into, a different one gets a record of its own, starting from
+    // what the two have shared until now; either way the file can be opened afterwards
+    pub fn resolve_fingerprint_collision(
+        &mut self,
+        collision: &FingerprintCollision,
+        same_book: bool,
+    ) {
+        self.fingerprint_collisions
+            .retain(|c| !c.is_about(collision));
+        if same_book {
+            self.fingerprint_aliases
+                .insert(collision.fingerprint.clone(), collision.record.clone());
+            self.save_fingerprint_aliases();
+        } else {
+            let (shared, _) = DocumentInfo::from_fingerprint(
+                &PathBuf::from(&self.preferences.syncable_data_directory),
+                &collision.record,
+            );
+            let mut info = self
+                .all_local_documents_info
+                .get(&collision.record)
+                .cloned()
+                .unwrap_or(shared);
+            info.fingerprint = collision.fingerprint.clone();
+            // worked out again from this file when it's opened
+            info.description.clear();
+            info.page_count = 0;
+            info.pdf_annotations_imported = false;
+            self.all_local_documents_info
+                .insert(collision.fingerprint.clone(), info);
+            // once there's a record under its own fingerprint, that's what it's opened with
+            if let Some(path_buf) = self.save_document_info(&collision.fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    self.just_saved.insert(s.to_string());
+                }
+            }
+        }
+    }
+
+    // a book that turns out to be another one: its record is folded into the other's, whose entries win wherever the
+    // two differ, and its files are filed under the other's record from then on
+    pub fn merge_books(&mut self, from: &str, into: &str) {
+        // its open windows would go on saving to the old record
+        if self.already_loaded(from.to_string()).is_some() {
+            let description = self
+                .all_local_documents_info
+                .get(from)
+                .map(|info| info.description.clone())
+                .unwrap_or_default();
+            self.warnings.push_back(format!(
+                "\"{}\" has been opened since PDF Student started, so it can only be merged into another book after a restart. Other books can be merged into it now.",
+                description
+            ));
+            return;
+        }
+
+        let data_dir = PathBuf::from(&self.preferences.syncable_data_directory);
+        let record = |fingerprint: &str| {
+            self.all_local_documents_info
+                .get(fingerprint)
+                .cloned()
+                .unwrap_or_else(|| DocumentInfo::from_fingerprint(&data_dir, fingerprint).0)
+        };
+        let (ours, theirs) = (record(into), record(from));
+        let (mut merged, _conflicts) =
+            sync_merge::merge_document_info(&DocumentInfo::blank(into), &ours, &theirs);
+        // the old record may have needed the book it's merged into, which mustn't now need itself
+        merged.prerequisites.remove(into);
+        for doc in self.loaded_documents.iter_mut() {
+            if doc.fingerprint == into {
+                doc.generate_reverse_bookmarks(&merged);
+            }
+        }
+        self.all_local_documents_info
+            .insert(into.to_string(), merged);
+        self.all_local_documents_info.remove(from);
+        self.sync_conflicts.retain(|c| c.fingerprint != from);
+
+        // books needing the old record now need the one it's merged into
+        let mut needers = Vec::<Fingerprint>::new();
+        for (fingerprint, info) in self.all_local_documents_info.iter_mut() {
+            if let Some(prerequisite) = info.prerequisites.remove(from) {
+                if fingerprint != into {
+                    info.prerequisites
+                        .entry(into.to_string())
+                        .or_insert(prerequisite);
+                }
+                needers.push(fingerprint.clone());
+            }
+        }
+        needers.push(into.to_string());
+        for fingerprint in needers {
+            if let Some(path_buf) = self.save_document_info(&fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    self.just_saved.insert(s.to_string());
+                }
+            }
+        }
+
+        let marks: Vec<(String, PageNum)> = self
+            .global_marks
+            .iter()
+            .filter(|(_, mark)| mark.fingerprint == from)
+            .map(|(letter, mark)| (letter.clone(), mark.page))
+            .collect();
+        for (letter, page) in marks {
+            self.set_global_mark(&letter, into, page);
+        }
+
+        for (_, record) in self.fingerprint_aliases.iter_mut() {
+            if record == from {
+                *record = into.to_string();
+            }
+        }
+        self.fingerprint_aliases
+            .insert(from.to_string(), into.to_string());
+        self.save_fingerprint_aliases();
+
+        // where the old record's files were seen, put at the front so they're tried after the places the book
+        // itself was, and the book's "last seen" place stays its own
+        if let Some(paths) = self.recent_document_locations.locations.remove(from) {
+            let locations = self
+                .recent_document_locations
+                .locations
+                .entry(into.to_string())
+                .or_insert_with(Vector::<String>::new);
+            for path in paths {
+                if !locations.iter().any(|known| *known == path) {
+                    locations.push_front(path);
+                }
+            }
+        }
+        self.recent_document_locations
+            .fingerprints
+            .retain(|fingerprint| fingerprint != from);
+        self.recent_document_locations.file_sizes.remove(from);
+
+        let mut path_buf = data_dir;
+        path_buf.push(from);
+        path_buf.set_extension("json");
+        if path_buf.exists() {
+            self.archive_info_file(&path_buf, "merged_books");
+        }
+    }
+
     pub fn open_book_with_fingerprint(&mut self, fp: &str) -> Option<usize> {
         // check if it's already loaded in documents
         // work through list of known locations, most recent first, trying to load file
@@ -708,8 +1195,9 @@ impl AppState {
         //         return Some(i)
         //     }
         // }
+        // the most recent is at the back, as `put_thing_at_back_of_set_vector` keeps them
         if let Some(locations) = self.recent_document_locations.locations.get(fp) {
-            for path in locations.clone().iter() {
+            for path in locations.clone().iter().rev() {
                 if let Some(idx) = self.load_file(&Path::new(path)) {
                     return Some(idx);
                 }
@@ -718,92 +1206,585 @@ impl AppState {
         None
     }
 
-    fn reload_doc_info(&mut self, path_buf: &PathBuf) {
-        match fs::read_to_string(path_buf) {
-            Ok(serialized) => match serde_json::from_str(&serialized) {
-                Ok(loaded_info) => {
-                    let info: DocumentInfo = loaded_info;
-                    for doc in &mut self.loaded_documents.iter_mut() {
-                        if doc.fingerprint == info.fingerprint {
-                            //doc.info = info.clone();
-                            doc.generate_reverse_bookmarks(&info);
-                            break;
-                        }
-                    }
-                    self.all_local_documents_info
-                        .insert(info.fingerprint.clone(), info);
-                }
-                Err(e) => println!("error reloading doc info: {}", e),
-            },
-            Err(e) => println!("doc info reloading error: {}", e),
+    // what the search for a book that's moved goes on
+    fn wanted_book(&self, fingerprint: &str) -> WantedBook {
+        WantedBook {
+            fingerprint: fingerprint.to_string(),
+            file_size: self
+                .recent_document_locations
+                .file_sizes
+                .get(fingerprint)
+                .copied(),
+            page_count: self
+                .all_local_documents_info
+                .get(fingerprint)
+                .map_or(0, |info| info.page_count),
+            aliases: self.fingerprint_aliases.clone(),
         }
     }
 
-    pub fn save_document_info(&self, fingerprint: &str) -> Option<PathBuf> {
-        let data_dir = self.preferences.syncable_data_directory.clone();
-        if fs::create_dir_all(&*data_dir).is_err() {
-            println!("Unable to create data directory");
-            return None;
+    // once a book's turned up somewhere new, the places it used to be aren't worth trying any more
+    fn forget_missing_locations(&mut self, fingerprint: &str) {
+        if let Some(locations) = self
+            .recent_document_locations
+            .locations
+            .get_mut(fingerprint)
+        {
+            locations.retain(|path| Path::new(path).exists());
         }
+    }
 
-        let mut path_buf = PathBuf::from(&*data_dir);
-
-        let doc_info = &self.all_local_documents_info.get(fingerprint).unwrap();
+    // the user has pointed out a file as being a book that couldn't be found, so it's filed under that book's
+    // record, even if it's a different printing, unless it's already known as another book
+    pub fn locate_book(&mut self, fingerprint: &str, path: &Path) -> Option<usize> {
+        let path_string = canonical_path_string(path)?;
+        let identity = match PdfDocument::open(&path_string) {
+            Ok(pdf_doc) => fingerprint::identify(&pdf_doc, &path_string),
+            Err(e) => {
+                println!("Error opening PDF file: {}", e);
+                return None;
+            }
+        };
+        let known_as = match self.fingerprint_aliases.get(&identity.fingerprint) {
+            Some(record) => Some(record.clone()),
+            None if self.has_record(&identity.fingerprint) => Some(identity.fingerprint.clone()),
+            None => None,
+        };
+        match known_as {
+            Some(record) if record != fingerprint => {
+                self.warnings.push_back(format!(
+                    "{} is \"{}\", not \"{}\", so it wasn't filed under that book.",
+                    user_facing_path(&path_string),
+                    self.book_description(&record),
+                    self.book_description(fingerprint)
+                ));
+                return None;
+            }
+            Some(_) => {}
+            None => {
+                self.fingerprint_aliases
+                    .insert(identity.fingerprint, fingerprint.to_string());
+                self.save_fingerprint_aliases();
+            }
+        }
 
-        path_buf.push(fingerprint);
+        let doc_idx = self.load_file(path)?;
+        self.missing_books
+            .retain(|book| book.fingerprint != fingerprint);
+        self.forget_missing_locations(fingerprint);
+        Some(doc_idx)
+    }
 
-        path_buf.set_extension("json");
+    fn book_description(&self, fingerprint: &str) -> String {
+        self.all_local_documents_info
+            .get(fingerprint)
+            .map(|info| info.description.clone())
+            .unwrap_or_else(|| fingerprint.to_string())
+    }
 
-        let serialized = serde_json::to_string(&doc_info).unwrap();
+    // returns the path written, so the filesystem watcher's notification about it can be ignored
+    fn save_global_marks(&mut self) -> PathBuf {
+        let path = global_marks::marks_path(&self.preferences.syncable_data_directory);
+        self.global_marks = global_marks::save_marks(&path, &self.global_marks);
+        path
+    }
 
-        if let Err(e) = fs::write(&path_buf, &serialized[..]) {
-            println!("Error writing file: {}", e);
+    fn set_global_mark(&mut self, letter: &str, fingerprint: &str, page: PageNum) {
+        self.global_marks
+            .insert(letter.to_string(), global_marks::new_mark(fingerprint, page));
+        let path_buf = self.save_global_marks();
+        if let Some(s) = path_buf.to_str() {
+            self.just_saved.insert(s.to_string());
         }
+    }
 
-        println!("SAVED {:?} {}", path_buf, doc_info.description);
-        Some(path_buf)
+    fn sync_base_path(&self, fingerprint: &str) -> Option<PathBuf> {
+        let dir = self.local_data_directory.clone()?;
+        let mut path = PathBuf::from(&*dir);
+        path.push("sync_base");
+        path.push(fingerprint);
+        path.set_extension("json");
+        Some(path)
     }
 
-    fn save_all_doc_data(&mut self) {
-        for doc in self.loaded_documents.iter() {
-            if doc.doc_info_changed {
-                if let Some(path_buf) = self.save_document_info(&doc.fingerprint) {
-                    if let Some(s) = path_buf.to_str() {
-                        self.just_saved.insert(s.to_string());
-                    }
+    // the last version of a book's info that this machine and the sync directory agreed on, used as the common ancestor when merging
+    fn load_sync_base(&self, fingerprint: &str) -> Option<DocumentInfo> {
+        let path = self.sync_base_path(fingerprint)?;
+        let serialized = fs::read_to_string(path).ok()?;
+        parse_document_info(&serialized).ok()
+    }
+
+    fn store_sync_base(&self, info: &DocumentInfo) {
+        if let Some(path) = self.sync_base_path(&info.fingerprint) {
+            if let Some(dir) = path.parent() {
+                if fs::create_dir_all(dir).is_err() {
+                    println!("Unable to create sync base directory");
+                    return;
                 }
             }
+            let serialized = serde_json::to_string(info).unwrap();
+            if let Err(e) = write_atomically(&path, &serialized[..]) {
+                println!("Error writing sync base: {}", e);
+            }
         }
+    }
 
-        if let Some(dir) = self.local_data_directory.clone() {
-            fs::create_dir_all(&*dir).expect("Unable to create data directory");
+    // three-way merge a version of a book's info found in the sync directory into what we have in memory
+    // returns true if the merged result differs from `theirs` and so should be written back
+    fn merge_in_doc_info(&mut self, theirs: DocumentInfo) -> bool {
+        let merged = self.merge_into_memory(&theirs);
+        self.store_sync_base(&theirs);
+        merged != theirs
+    }
 
-            let mut path = PathBuf::from(&*dir);
-            path.push("file_locations");
-            path.set_extension("json");
+    fn merge_into_memory(&mut self, theirs: &DocumentInfo) -> DocumentInfo {
+        let fingerprint = theirs.fingerprint.clone();
 
-            let serialized = serde_json::to_string(&self.recent_document_locations).unwrap();
+        let merged = match self.all_local_documents_info.get(&fingerprint) {
+            Some(ours) => {
+                let base = self
+                    .load_sync_base(&fingerprint)
+                    .unwrap_or_else(|| DocumentInfo::blank(&fingerprint));
+                let (merged, conflicting_fields) =
+                    sync_merge::merge_document_info(&base, ours, theirs);
 
-            if let Err(e) = fs::write(&path, &serialized[..]) {
-                println!("Error writing file: {}", e);
+                for conflict in sync_merge::conflicts_for(conflicting_fields, ours, theirs) {
+                    self.sync_conflicts.retain(|c| !c.is_about(&conflict));
+                    self.sync_conflicts.push_back(conflict);
+                }
+                merged
             }
+            None => theirs.clone(),
+        };
 
-            if let Some(s) = path.to_str() {
-                self.just_saved.insert(s.to_string());
+        for doc in &mut self.loaded_documents.iter_mut() {
+            if doc.fingerprint == fingerprint {
+                doc.generate_reverse_bookmarks(&merged);
+                break;
             }
+        }
 
-            let mut prefs_path = PathBuf::from(&*dir);
-            prefs_path.push("preferences");
-            prefs_path.set_extension("json");
-
-            let serialized = serde_json::to_string(&self.preferences).unwrap();
+        self.all_local_documents_info
+            .insert(fingerprint, merged.clone());
+        merged
+    }
 
-            if let Err(e) = fs::write(prefs_path, &serialized[..]) {
-                println!("Error writing file: {}", e);
+    // merge a sync client's "conflicted copy" of a book's info into the canonical version, then move it out of the way
+    // returns the fingerprint of the book whose info now needs saving
+    fn fold_in_conflicted_copy(&mut self, path: &Path) -> Option<Fingerprint> {
+        let serialized = match fs::read_to_string(path) {
+            Ok(serialized) => serialized,
+            Err(e) => {
+                println!("unable to read conflicted copy {:?}: {}", path, e);
+                return None;
+            }
+        };
+        let theirs: DocumentInfo = match parse_document_info(&serialized) {
+            Ok(info) => info,
+            Err(e) => {
+                // probably still being written by the sync client, another notification will follow
+                println!("unable to parse conflicted copy {:?}: {}", path, e);
+                return None;
             }
+        };
 
-            if let Some(s) = path.to_str() {
-                self.just_saved.insert(s.to_string());
+        let fingerprint = theirs.fingerprint.clone();
+        if !self.all_local_documents_info.contains_key(&fingerprint) {
+            let (canonical, warning) = DocumentInfo::from_fingerprint(
+                &PathBuf::from(&self.preferences.syncable_data_directory),
+                &fingerprint,
+            );
+            if let Some(warning) = warning {
+                self.warnings.push_back(warning);
+            }
+            self.all_local_documents_info
+                .insert(fingerprint.clone(), canonical);
+        }
+
+        // the conflicted copy branched off from the canonical file, so the sync base is still the right common ancestor
+        self.merge_into_memory(&theirs);
+        self.archive_conflicted_copy(path);
+
+        Some(fingerprint)
+    }
+
+    fn archive_conflicted_copy(&self, path: &Path) {
+        self.archive_info_file(path, "merged_conflicts");
+    }
+
+    // moved out of the sync directory to `folder` in the local data directory, rather than deleted
+    fn archive_info_file(&self, path: &Path, folder: &str) {
+        let archive_path = self.local_data_directory.clone().and_then(|dir| {
+            let mut archive = PathBuf::from(&*dir);
+            archive.push(folder);
+            fs::create_dir_all(&archive).ok()?;
+            archive.push(path.file_name()?);
+            Some(archive)
+        });
+
+        if let Some(archive_path) = archive_path {
+            // renaming fails across filesystems, fall back to copying
+            if fs::rename(path, &archive_path).is_ok() {
+                return;
+            }
+            if let Err(e) = fs::copy(path, &archive_path) {
+                println!("unable to archive {:?}: {}", path, e);
+                return;
+            }
+        }
+        if let Err(e) = fs::remove_file(path) {
+            println!("unable to remove {:?}: {}", path, e);
+        }
+    }
+
+    // sweep up conflicted copies that arrived while we weren't running
+    fn fold_in_conflicted_copies(&mut self) {
+        let entries = match fs::read_dir(&self.preferences.syncable_data_directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let conflicted: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| sync_merge::conflicted_copy_of(path).is_some())
+            .collect();
+
+        for path in conflicted {
+            if self.fold_in_conflicted_shared_file(&path) {
+                continue;
+            }
+            if let Some(fingerprint) = self.fold_in_conflicted_copy(&path) {
+                self.save_document_info(&fingerprint);
+            }
+        }
+    }
+
+    // a conflicted copy of the global marks or the fingerprint aliases is merged like the file it's a copy of, then
+    // archived like a book's; false if it's a copy of something else
+    fn fold_in_conflicted_shared_file(&mut self, path: &Path) -> bool {
+        let original = match sync_merge::conflicted_copy_of(path) {
+            Some(original) => original,
+            None => return false,
+        };
+        let is_marks = original == global_marks::GLOBAL_MARKS_FILE;
+        if !is_marks && original != fingerprint::FINGERPRINT_ALIASES_FILE {
+            return false;
+        }
+
+        let serialized = match fs::read_to_string(path) {
+            Ok(serialized) => serialized,
+            Err(e) => {
+                println!("unable to read conflicted copy {:?}: {}", path, e);
+                return true;
+            }
+        };
+        // a copy that doesn't parse is probably still being written by the sync client, another notification will follow
+        if is_marks {
+            match serde_json::from_str::<GlobalMarks>(&serialized) {
+                Ok(theirs) => {
+                    self.global_marks = global_marks::merge_marks(&self.global_marks, &theirs);
+                    let path_buf = self.save_global_marks();
+                    if let Some(s) = path_buf.to_str() {
+                        self.just_saved.insert(s.to_string());
+                    }
+                }
+                Err(e) => {
+                    println!("unable to parse conflicted copy {:?}: {}", path, e);
+                    return true;
+                }
+            }
+        } else {
+            match serde_json::from_str::<FingerprintAliases>(&serialized) {
+                Ok(theirs) => {
+                    self.fingerprint_aliases =
+                        fingerprint::merge_aliases(&self.fingerprint_aliases, &theirs);
+                    self.save_fingerprint_aliases();
+                }
+                Err(e) => {
+                    println!("unable to parse conflicted copy {:?}: {}", path, e);
+                    return true;
+                }
+            }
+        }
+        self.archive_conflicted_copy(path);
+        true
+    }
+
+    // returns the fingerprint of the book if our merged version should be saved back to the sync directory
+    fn reload_doc_info(&mut self, path_buf: &PathBuf) -> Option<Fingerprint> {
+        match fs::read_to_string(path_buf) {
+            Ok(serialized) => match parse_document_info(&serialized) {
+                Ok(loaded_info) => {
+                    let info: DocumentInfo = loaded_info;
+                    let fingerprint = info.fingerprint.clone();
+                    if self.merge_in_doc_info(info) {
+                        return Some(fingerprint);
+                    }
+                }
+                Err(e) => println!("error reloading doc info: {}", e),
+            },
+            Err(e) => println!("doc info reloading error: {}", e),
+        }
+        None
+    }
+
+    fn text_index_directory(&self) -> Option<PathBuf> {
+        let dir = self.local_data_directory.clone()?;
+        let mut path = PathBuf::from(&*dir);
+        path.push("text_index");
+        Some(path)
+    }
+
+    fn backups_directory(&self) -> Option<PathBuf> {
+        let dir = self.local_data_directory.clone()?;
+        let mut path = PathBuf::from(&*dir);
+        path.push("backups");
+        Some(path)
+    }
+
+    // bring back the tags and bookmarks from an earlier version of a book's info, the version being replaced gets backed up in turn when it's saved
+    pub fn restore_backup(&mut self, fingerprint: &str, backup_path: &Path) {
+        let backup = match fs::read_to_string(backup_path) {
+            Ok(serialized) => match parse_document_info(&serialized) {
+                Ok(backup) => backup,
+                Err(e) => {
+                    self.warnings
+                        .push_back(format!("Couldn't restore {:?}: {}", backup_path, e));
+                    return;
+                }
+            },
+            Err(e) => {
+                self.warnings
+                    .push_back(format!("Couldn't restore {:?}: {}", backup_path, e));
+                return;
+            }
+        };
+
+        if let Some(info) = self.all_local_documents_info.get_mut(fingerprint) {
+            info.tags = backup.tags;
+            info.bookmarks = backup.bookmarks;
+
+            let info = info.clone();
+            for doc in &mut self.loaded_documents.iter_mut() {
+                if doc.fingerprint == fingerprint {
+                    doc.generate_reverse_bookmarks(&info);
+                }
+            }
+        }
+    }
+
+    // annotations read from the PDF file on another thread, added to whatever the book has gained in the meantime
+    fn add_imported_annotations(&mut self, fingerprint: &str, imported: &ImportedAnnotations) {
+        let info = match self.all_local_documents_info.get_mut(fingerprint) {
+            Some(info) if !info.pdf_annotations_imported => info,
+            _ => return,
+        };
+        for (page, highlights) in imported.highlights.iter() {
+            info.highlights
+                .entry(*page)
+                .or_insert_with(Vector::<Highlight>::new)
+                .append(highlights.clone());
+        }
+        for (page, notes) in imported.notes.iter() {
+            info.notes
+                .entry(*page)
+                .or_insert_with(Vector::<Note>::new)
+                .append(notes.clone());
+        }
+        info.pdf_annotations_imported = true;
+        if imported.count > 0 {
+            println!("Imported {} annotations from the PDF file", imported.count);
+        }
+
+        if let Some(path_buf) = self.save_document_info(fingerprint) {
+            if let Some(s) = path_buf.to_str() {
+                self.just_saved.insert(s.to_string());
+            }
+        }
+    }
+
+    // a name that's already in use is moved to the new page
+    pub fn add_named_bookmark(&mut self, fingerprint: &str, name: &str, page: PageNum) {
+        if let Some(info) = self.all_local_documents_info.get_mut(fingerprint) {
+            info.add_bookmark(name, page);
+
+            let info = info.clone();
+            for doc in &mut self.loaded_documents.iter_mut() {
+                if doc.fingerprint == fingerprint {
+                    doc.generate_reverse_bookmarks(&info);
+                }
+            }
+        }
+    }
+
+    // an empty note is deleted rather than saved
+    pub fn set_note(&mut self, edit: &NoteEdit, text: &str) {
+        if let Some(info) = self.all_local_documents_info.get_mut(&edit.fingerprint) {
+            let notes = info
+                .notes
+                .entry(edit.page)
+                .or_insert_with(Vector::<Note>::new);
+            match edit.idx {
+                Some(idx) if idx < notes.len() => {
+                    if text.trim().is_empty() {
+                        notes.remove(idx);
+                    } else {
+                        notes[idx].text = text.to_string();
+                    }
+                }
+                _ => {
+                    if !text.trim().is_empty() {
+                        notes.push_back(Note {
+                            region: edit.region,
+                            text: text.to_string(),
+                        });
+                    }
+                }
+            }
+            if notes.is_empty() {
+                info.notes.remove(&edit.page);
+            }
+        }
+    }
+
+    pub fn set_tag_table(&mut self, fingerprint: &Option<Fingerprint>, rows: &Vector<TagRow>) {
+        match fingerprint {
+            Some(fp) => {
+                if let Some(info) = self.all_local_documents_info.get_mut(fp) {
+                    tag_table::set_book_tags(info, rows, &self.preferences.default_tag_table);
+                }
+            }
+            None => tag_table::set_default_tags(&mut self.preferences.default_tag_table, rows),
+        }
+    }
+
+    pub fn set_prerequisites(
+        &mut self,
+        fingerprint: &str,
+        rows: &Vector<prerequisites::PrerequisiteRow>,
+    ) {
+        if let Some(info) = self.all_local_documents_info.get_mut(fingerprint) {
+            prerequisites::set_prerequisites(info, rows);
+        }
+    }
+
+    pub fn resolve_sync_conflict(&mut self, conflict: &SyncConflict, take_theirs: bool) {
+        self.sync_conflicts.retain(|c| !c.is_about(conflict));
+
+        if take_theirs {
+            if let Some(info) = self.all_local_documents_info.get_mut(&conflict.fingerprint) {
+                conflict.field.copy_value(&conflict.theirs, info);
+            }
+            if let Some(info) = self.all_local_documents_info.get(&conflict.fingerprint) {
+                let info = info.clone();
+                for doc in &mut self.loaded_documents.iter_mut() {
+                    if doc.fingerprint == conflict.fingerprint {
+                        doc.generate_reverse_bookmarks(&info);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn save_document_info(&mut self, fingerprint: &str) -> Option<PathBuf> {
+        let data_dir = self.preferences.syncable_data_directory.clone();
+        if fs::create_dir_all(&*data_dir).is_err() {
+            println!("Unable to create data directory");
+            return None;
+        }
+
+        let mut path_buf = PathBuf::from(&*data_dir);
+
+        path_buf.push(fingerprint);
+
+        path_buf.set_extension("json");
+
+        // the sync client may have delivered a newer version we haven't been notified about yet, fold it in rather than overwriting it
+        if let Ok(serialized) = fs::read_to_string(&path_buf) {
+            match parse_document_info(&serialized) {
+                Ok(theirs) => {
+                    if Some(&theirs) != self.load_sync_base(fingerprint).as_ref() {
+                        self.merge_in_doc_info(theirs);
+                    }
+                }
+                Err(SchemaError::TooNew(version)) => {
+                    println!("not saving over newer format {} file {:?}", version, path_buf);
+                    return None;
+                }
+                Err(e) => {
+                    // never overwrite something we couldn't read, it may be the only copy of someone's tags
+                    let aside = doc_info_schema::set_aside_unreadable(&path_buf)?;
+                    self.warnings.push_back(format!(
+                        "{:?} couldn't be read ({}), so it has been kept as {:?} before saving over it.",
+                        path_buf, e, aside
+                    ));
+                }
+            }
+        }
+
+        let doc_info = self.all_local_documents_info.get(fingerprint)?.clone();
+
+        let serialized = serde_json::to_string(&doc_info).unwrap();
+
+        if let Some(backups_dir) = self.backups_directory() {
+            persistence::back_up(&backups_dir, fingerprint, &path_buf);
+        }
+
+        if let Err(e) = write_atomically(&path_buf, &serialized[..]) {
+            println!("Error writing file: {}", e);
+            return None;
+        }
+        self.store_sync_base(&doc_info);
+
+        println!("SAVED {:?} {}", path_buf, doc_info.description);
+        Some(path_buf)
+    }
+
+    fn save_all_doc_data(&mut self) {
+        let changed: Vec<Fingerprint> = self
+            .loaded_documents
+            .iter()
+            .filter(|doc| doc.doc_info_changed)
+            .map(|doc| doc.fingerprint.clone())
+            .collect();
+        for fingerprint in changed {
+            if let Some(path_buf) = self.save_document_info(&fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    self.just_saved.insert(s.to_string());
+                }
+            }
+        }
+
+        if let Some(dir) = self.local_data_directory.clone() {
+            fs::create_dir_all(&*dir).expect("Unable to create data directory");
+
+            let mut path = PathBuf::from(&*dir);
+            path.push("file_locations");
+            path.set_extension("json");
+
+            let serialized = serde_json::to_string(&self.recent_document_locations).unwrap();
+
+            if let Err(e) = write_atomically(&path, &serialized[..]) {
+                println!("Error writing file: {}", e);
+            }
+
+            if let Some(s) = path.to_str() {
+                self.just_saved.insert(s.to_string());
+            }
+
+            let mut prefs_path = PathBuf::from(&*dir);
+            prefs_path.push("preferences");
+            prefs_path.set_extension("json");
+
+            let serialized = serde_json::to_string(&self.preferences).unwrap();
+
+            if let Err(e) = write_atomically(&prefs_path, &serialized[..]) {
+                println!("Error writing file: {}", e);
+            }
+
+            if let Some(s) = prefs_path.to_str() {
+                self.just_saved.insert(s.to_string());
             }
         }
     }
@@ -822,13 +1803,132 @@ impl AppState {
 struct Delegate {
     window_count: usize,
     windows_to_open: Vec<usize>,
+    sync_conflicts_window: Option<WindowId>,
+    fingerprint_collisions_window: Option<WindowId>,
+    missing_books_window: Option<WindowId>,
+    warnings_window: Option<WindowId>,
+    review_window: Option<WindowId>,
+    library_search_window: Option<WindowId>,
+    // books whose text index is being loaded or built, so opening a second window doesn't start another
+    // one that failed stays here, rather than being retried by every new window
+    indexing: HashSet<Fingerprint>,
+    // books whose PDF annotations are being read, likewise
+    importing_annotations: HashSet<Fingerprint>,
+    // books being looked for in the library folders and elsewhere, after they weren't where they were last seen
+    relocating: HashSet<Fingerprint>,
+    // the book the open dialog from the missing books window is asking about
+    locating: Option<Fingerprint>,
+    // for work done on other threads, like scanning the library folders
+    event_sink: ExtEventSink,
 }
 
 impl Delegate {
-    fn new(windows: Vec<usize>) -> Self {
+    fn new(windows: Vec<usize>, event_sink: ExtEventSink) -> Self {
         Self {
             window_count: 0,
             windows_to_open: windows,
+            sync_conflicts_window: None,
+            fingerprint_collisions_window: None,
+            missing_books_window: None,
+            warnings_window: None,
+            review_window: None,
+            library_search_window: None,
+            indexing: HashSet::<Fingerprint>::new(),
+            importing_annotations: HashSet::<Fingerprint>::new(),
+            relocating: HashSet::<Fingerprint>::new(),
+            locating: None,
+            event_sink,
+        }
+    }
+
+    // brings forward a window already showing the book, or opens the book at the page
+    // false if the book couldn't be found on this machine
+    fn go_to_book_page(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        data: &mut AppState,
+        fingerprint: &str,
+        page: PageNum,
+    ) -> bool {
+        let open_window = data
+            .pdf_view_windows
+            .iter()
+            .find(|(_, fp)| **fp == fingerprint)
+            .map(|(id, _)| *id);
+        if let Some(id) = open_window {
+            ctx.submit_command(GO_TO_BOOKMARK.with(page).to(id));
+            ctx.submit_command(sys_cmds::SHOW_WINDOW.to(id));
+        } else if let Some(doc_idx) = data.open_book_with_fingerprint(fingerprint) {
+            // new views start on the book's most recent page
+            if let Some(info) = data.all_local_documents_info.get_mut(fingerprint) {
+                info.most_recent_page = page;
+            }
+            ctx.new_window(make_pdf_view_window(data, doc_idx, None));
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn go_to_global_mark(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        data: &mut AppState,
+        letter: &str,
+        mark: &GlobalMark,
+    ) {
+        if !self.go_to_book_page(ctx, data, &mark.fingerprint, mark.page) {
+            let description = data.book_description(&mark.fingerprint);
+            data.warnings.push_back(format!(
+                "Global mark {} is in \"{}\", which couldn't be found on this machine. Open it once from wherever it is now and the mark will work again.",
+                letter, description
+            ));
+            self.show_pending_prompts(ctx, data);
+        }
+    }
+
+    // shows the page at the front of the review queue, skipping books that can't be found, then puts the review window back in front
+    fn show_review_page(&mut self, ctx: &mut DelegateCtx, data: &mut AppState) {
+        while let Some(item) = data.review_queue.front().cloned() {
+            if self.go_to_book_page(ctx, data, &item.fingerprint, item.page) {
+                break;
+            }
+            data.review_queue.pop_front();
+            let description = data.book_description(&item.fingerprint);
+            data.warnings.push_back(format!(
+                "\"{}\" has pages due for review but couldn't be found on this machine, so they were skipped.",
+                description
+            ));
+            data.review_queue
+                .retain(|other| other.fingerprint != item.fingerprint);
+        }
+        if let Some(id) = self.review_window {
+            ctx.submit_command(sys_cmds::SHOW_WINDOW.to(id));
+        }
+        self.show_pending_prompts(ctx, data);
+    }
+
+    fn show_pending_prompts(&mut self, ctx: &mut DelegateCtx, data: &AppState) {
+        if !data.sync_conflicts.is_empty() && self.sync_conflicts_window.is_none() {
+            let win = sync_merge::make_sync_conflicts_window();
+            self.sync_conflicts_window = Some(win.id);
+            ctx.new_window(win);
+        }
+        if !data.fingerprint_collisions.is_empty() && self.fingerprint_collisions_window.is_none()
+        {
+            let win = fingerprint::make_fingerprint_collisions_window();
+            self.fingerprint_collisions_window = Some(win.id);
+            ctx.new_window(win);
+        }
+        if !data.missing_books.is_empty() && self.missing_books_window.is_none() {
+            let win = relocate::make_missing_books_window();
+            self.missing_books_window = Some(win.id);
+            ctx.new_window(win);
+        }
+        if !data.warnings.is_empty() && self.warnings_window.is_none() {
+            let win = warnings_window::make_warnings_window();
+            self.warnings_window = Some(win.id);
+            ctx.new_window(win);
         }
     }
 }
@@ -842,7 +1942,7 @@ impl AppDelegate<AppState> for Delegate {
     fn command(
         &mut self,
         ctx: &mut DelegateCtx,
-        _target: Target,
+        target: Target,
         cmd: &Command,
         data: &mut AppState,
         _env: &Env,
@@ -856,17 +1956,104 @@ impl AppDelegate<AppState> for Delegate {
                     ctx.new_window(make_pdf_view_window(data, doc_id, None));
                 }
             }
+            self.show_pending_prompts(ctx, data);
             //            println!("MESSAGE! {} {}", message, message.len());
             Handled::Yes
         } else if let Some(file_info) = cmd.get(sys_cmds::OPEN_FILE) {
             if let Some(doc_id) = data.load_file(&file_info.path()) {
                 ctx.new_window(make_pdf_view_window(data, doc_id, None));
             }
+            self.show_pending_prompts(ctx, data);
             Handled::Yes
         } else if let Some(fingerprint) = cmd.get(OPEN_BOOK_WITH_FINGERPRINT) {
             if let Some(doc_idx) = data.open_book_with_fingerprint(fingerprint) {
                 ctx.new_window(make_pdf_view_window(data, doc_idx, None));
+            } else if self.relocating.insert(fingerprint.clone()).is_none() {
+                // none of the places it's been seen, so it's looked for, and opened if it turns up
+                relocate::start_search(
+                    self.event_sink.clone(),
+                    data.wanted_book(fingerprint),
+                    relocate::places_to_look(library::library_folders(
+                        &data.preferences.library_folders,
+                    )),
+                    data.known_pdf_paths(),
+                );
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some((fingerprint, found)) = cmd.get(BOOK_RELOCATED) {
+            self.relocating.remove(fingerprint);
+            let doc_idx = found
+                .as_ref()
+                .and_then(|path| data.load_file(&Path::new(path)))
+                .filter(|idx| data.loaded_documents[*idx].fingerprint == *fingerprint);
+            if let Some(doc_idx) = doc_idx {
+                data.forget_missing_locations(fingerprint);
+                ctx.new_window(make_pdf_view_window(data, doc_idx, None));
+            } else if !data
+                .missing_books
+                .iter()
+                .any(|book| book.fingerprint == *fingerprint)
+            {
+                let last_seen = data
+                    .recent_document_locations
+                    .locations
+                    .get(fingerprint)
+                    .and_then(|locations| locations.last().cloned());
+                let summary = match last_seen {
+                    Some(path) => format!(
+                        "\"{}\"\nlast seen at {}",
+                        data.book_description(fingerprint),
+                        user_facing_path(&path)
+                    ),
+                    None => format!("\"{}\"", data.book_description(fingerprint)),
+                };
+                data.missing_books.push_back(MissingBook {
+                    fingerprint: fingerprint.clone(),
+                    summary,
+                });
             }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(LOCATE_BOOK) {
+            // the dialog has to belong to a window, which is the missing books window the request came from
+            self.locating = Some(fingerprint.clone());
+            let options = relocate::locate_dialog_options(&data.book_description(fingerprint));
+            ctx.submit_command(sys_cmds::SHOW_OPEN_PANEL.with(options).to(target));
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(LOCATED_BOOK_FILE) {
+            if let Some(fingerprint) = self.locating.take() {
+                if let Some(doc_idx) = data.locate_book(&fingerprint, &file_info.path()) {
+                    ctx.new_window(make_pdf_view_window(data, doc_idx, None));
+                }
+                if data.missing_books.is_empty() {
+                    if let Some(id) = self.missing_books_window {
+                        ctx.submit_command(sys_cmds::CLOSE_WINDOW.to(id));
+                    }
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some((collision, same_book)) = cmd.get(RESOLVE_FINGERPRINT_COLLISION) {
+            data.resolve_fingerprint_collision(collision, *same_book);
+            if data.fingerprint_collisions.is_empty() {
+                if let Some(id) = self.fingerprint_collisions_window {
+                    ctx.submit_command(sys_cmds::CLOSE_WINDOW.to(id));
+                }
+            }
+            if let Some(doc_id) = data.load_file(&Path::new(&collision.path)) {
+                ctx.new_window(make_pdf_view_window(data, doc_id, None));
+            }
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(CHOOSE_BOOK_TO_MERGE) {
+            ctx.new_window(fingerprint::make_merge_books_window(
+                data,
+                fingerprint.clone(),
+            ));
+            Handled::Yes
+        } else if let Some((from, into)) = cmd.get(MERGE_BOOKS) {
+            data.merge_books(from, into);
+            self.show_pending_prompts(ctx, data);
             Handled::Yes
         } else if let Some(doc_idx) = cmd.get(crate::pdf_view::SHOW_BOOK_INFO) {
             //let pref_win =;
@@ -905,17 +2092,300 @@ impl AppDelegate<AppState> for Delegate {
                     }
                 }
             }
+            self.show_pending_prompts(ctx, data);
 
             Handled::Yes
         } else if let Some(path_buf) = cmd.get(SYNCABLE_DIRECTORY_FILES_CHANGED) {
+            // temporary files from atomic writes, ours or another program's
+            if path_buf.extension().map_or(true, |ext| ext != "json") {
+                return Handled::Yes;
+            }
             if let Some(s) = path_buf.to_str() {
                 if data.just_saved.contains(&s.to_string()) {
                     data.just_saved.remove(&s.to_string());
                     return Handled::Yes;
                 }
             }
-            println!("Reloading, as it's changed on disk: {:?}", &path_buf);
-            data.reload_doc_info(&path_buf);
+            if global_marks::is_marks_file(path_buf) {
+                data.global_marks = global_marks::merge_marks(
+                    &data.global_marks,
+                    &global_marks::load_marks(path_buf),
+                );
+                return Handled::Yes;
+            }
+            if fingerprint::is_aliases_file(path_buf) {
+                data.fingerprint_aliases = fingerprint::merge_aliases(
+                    &data.fingerprint_aliases,
+                    &fingerprint::load_aliases(path_buf),
+                );
+                return Handled::Yes;
+            }
+            if data.fold_in_conflicted_shared_file(path_buf) {
+                return Handled::Yes;
+            }
+            let needs_saving = if sync_merge::conflicted_copy_fingerprint(path_buf).is_some() {
+                println!("Merging in sync client's conflicted copy: {:?}", &path_buf);
+                data.fold_in_conflicted_copy(path_buf)
+            } else {
+                println!("Reloading, as it's changed on disk: {:?}", &path_buf);
+                data.reload_doc_info(&path_buf)
+            };
+            if let Some(fingerprint) = needs_saving {
+                // our side had changes the other machine hasn't seen yet, write the merged version back
+                if let Some(path_buf) = data.save_document_info(&fingerprint) {
+                    if let Some(s) = path_buf.to_str() {
+                        data.just_saved.insert(s.to_string());
+                    }
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some((letter, fingerprint, page)) = cmd.get(GLOBAL_MARK_KEY) {
+            match data.global_marks.get(letter).filter(|mark| mark.is_set()).cloned() {
+                Some(mark) => self.go_to_global_mark(ctx, data, letter, &mark),
+                None => data.set_global_mark(letter, fingerprint, *page),
+            }
+            Handled::Yes
+        } else if let Some((letter, fingerprint, page)) = cmd.get(SET_GLOBAL_MARK) {
+            data.set_global_mark(letter, fingerprint, *page);
+            Handled::Yes
+        } else if let Some((fingerprint, page)) = cmd.get(CLEAR_GLOBAL_MARKS) {
+            let letters: Vec<String> = data
+                .global_marks
+                .iter()
+                .filter(|(_, mark)| mark.fingerprint == *fingerprint && mark.page == *page)
+                .map(|(letter, _)| letter.clone())
+                .collect();
+            if !letters.is_empty() {
+                for letter in letters {
+                    data.global_marks.insert(letter, global_marks::cleared_mark());
+                }
+                let path_buf = data.save_global_marks();
+                if let Some(s) = path_buf.to_str() {
+                    data.just_saved.insert(s.to_string());
+                }
+            }
+            Handled::Yes
+        } else if cmd.is(REVIEW_DUE_PAGES) {
+            data.review_queue =
+                review::due_pages(&data.all_local_documents_info, data.preferences.review_tag);
+            if self.review_window.is_none() {
+                let win = review::make_review_window();
+                self.review_window = Some(win.id);
+                ctx.new_window(win);
+            }
+            self.show_review_page(ctx, data);
+            Handled::Yes
+        } else if let Some(grade) = cmd.get(GRADE_REVIEW) {
+            if let Some(item) = data.review_queue.pop_front() {
+                if let Some(info) = data.all_local_documents_info.get_mut(&item.fingerprint) {
+                    review::grade_page(info, item.page, *grade);
+                }
+                if let Some(path_buf) = data.save_document_info(&item.fingerprint) {
+                    if let Some(s) = path_buf.to_str() {
+                        data.just_saved.insert(s.to_string());
+                    }
+                }
+            }
+            self.show_review_page(ctx, data);
+            Handled::Yes
+        } else if cmd.is(SKIP_REVIEW) {
+            data.review_queue.pop_front();
+            self.show_review_page(ctx, data);
+            Handled::Yes
+        } else if cmd.is(SCAN_LIBRARY) {
+            // folders may have been added in the preferences since the last scan
+            data.watch_library_folders();
+            data.scan_library(self.event_sink.clone());
+            Handled::Yes
+        } else if let Some(path_buf) = cmd.get(LIBRARY_FILE_CHANGED) {
+            library::start_scan(
+                self.event_sink.clone(),
+                vec![path_buf.clone()],
+                data.known_pdf_paths(),
+            );
+            Handled::Yes
+        } else if let Some(scanned) = cmd.get(LIBRARY_SCANNED) {
+            data.register_scanned_books(scanned);
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(INDEX_DOCUMENT) {
+            if !self.indexing.contains(fingerprint) {
+                let filepath = data
+                    .loaded_documents
+                    .iter()
+                    .find(|doc| doc.fingerprint == *fingerprint)
+                    .map(|doc| doc.filepath.clone());
+                if let (Some(filepath), Some(index_dir)) = (filepath, data.text_index_directory()) {
+                    self.indexing.insert(fingerprint.clone());
+                    text_index::load_or_build(
+                        self.event_sink.clone(),
+                        fingerprint.clone(),
+                        filepath,
+                        index_dir,
+                    );
+                }
+            }
+            Handled::Yes
+        } else if let Some((fingerprint, index)) = cmd.get(TEXT_INDEX_READY) {
+            self.indexing.remove(fingerprint);
+            for doc in data.loaded_documents.iter_mut() {
+                if doc.fingerprint == *fingerprint {
+                    doc.text_index = Some(index.clone());
+                }
+            }
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(IMPORT_PDF_ANNOTATIONS) {
+            let filepath = data
+                .loaded_documents
+                .iter()
+                .find(|doc| doc.fingerprint == *fingerprint)
+                .map(|doc| doc.filepath.clone());
+            let info = data
+                .all_local_documents_info
+                .get(fingerprint)
+                .filter(|info| !info.pdf_annotations_imported);
+            if let (Some(filepath), Some(info)) = (filepath, info) {
+                if self.importing_annotations.insert(fingerprint.clone()).is_none() {
+                    pdf_annotations::start_import(
+                        self.event_sink.clone(),
+                        fingerprint.clone(),
+                        filepath,
+                        tag_table::tag_table(info, &data.preferences.default_tag_table),
+                    );
+                }
+            }
+            Handled::Yes
+        } else if let Some((fingerprint, imported)) = cmd.get(PDF_ANNOTATIONS_IMPORTED) {
+            self.importing_annotations.remove(fingerprint);
+            data.add_imported_annotations(fingerprint, imported);
+            Handled::Yes
+        } else if cmd.is(SHOW_LIBRARY_SEARCH) {
+            match self.library_search_window {
+                Some(id) => ctx.submit_command(sys_cmds::SHOW_WINDOW.to(id)),
+                None => {
+                    let win = library_search::make_library_search_window();
+                    self.library_search_window = Some(win.id);
+                    ctx.new_window(win);
+                }
+            }
+            Handled::Yes
+        } else if cmd.is(START_LIBRARY_SEARCH) {
+            data.search_library(self.event_sink.clone());
+            Handled::Yes
+        } else if let Some(progress) = cmd.get(LIBRARY_SEARCH_PROGRESS) {
+            data.library_search
+                .add_progress(progress, &data.all_local_documents_info);
+            Handled::Yes
+        } else if let Some(hit) = cmd.get(OPEN_LIBRARY_HIT) {
+            match data.open_book_with_fingerprint(&hit.fingerprint) {
+                Some(doc_idx) => ctx.new_window(library_search::make_hit_window(data, doc_idx, hit)),
+                None => {
+                    let description = data.book_description(&hit.fingerprint);
+                    data.warnings.push_back(format!(
+                        "\"{}\" couldn't be found on this machine any more, it may have been moved or deleted since it was searched.",
+                        description
+                    ));
+                    self.show_pending_prompts(ctx, data);
+                }
+            }
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(SHOW_BACKUPS) {
+            if let Some(backups_dir) = data.backups_directory() {
+                let description = data
+                    .all_local_documents_info
+                    .get(fingerprint)
+                    .map(|info| info.description.clone())
+                    .unwrap_or_default();
+                ctx.new_window(backups_window::make_backups_window(
+                    &description,
+                    fingerprint,
+                    persistence::list_backups(&backups_dir, fingerprint),
+                ));
+            }
+            Handled::Yes
+        } else if let Some((fingerprint, backup_path)) = cmd.get(RESTORE_BACKUP) {
+            data.restore_backup(fingerprint, backup_path);
+            if let Some(path_buf) = data.save_document_info(fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    data.just_saved.insert(s.to_string());
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some(edit) = cmd.get(EDIT_NOTE) {
+            ctx.new_window(notes::make_note_editor_window(data, edit.clone()));
+            Handled::Yes
+        } else if let Some((edit, text)) = cmd.get(SAVE_NOTE) {
+            data.set_note(edit, text);
+            if let Some(path_buf) = data.save_document_info(&edit.fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    data.just_saved.insert(s.to_string());
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some((fingerprint, page)) = cmd.get(ADD_NAMED_BOOKMARK) {
+            ctx.new_window(bookmarks::make_bookmark_name_window(
+                data,
+                fingerprint.clone(),
+                *page,
+            ));
+            Handled::Yes
+        } else if let Some((fingerprint, name, page)) = cmd.get(SAVE_NAMED_BOOKMARK) {
+            data.add_named_bookmark(fingerprint, name, *page);
+            if let Some(path_buf) = data.save_document_info(fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    data.just_saved.insert(s.to_string());
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(EDIT_PREREQUISITES) {
+            ctx.new_window(prerequisites::make_prerequisites_window(
+                data,
+                fingerprint.clone(),
+            ));
+            Handled::Yes
+        } else if let Some((fingerprint, rows)) = cmd.get(SAVE_PREREQUISITES) {
+            data.set_prerequisites(fingerprint, rows);
+            if let Some(path_buf) = data.save_document_info(fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    data.just_saved.insert(s.to_string());
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if cmd.is(SHOW_READING_ORDER) {
+            ctx.new_window(prerequisites::make_reading_order_window());
+            Handled::Yes
+        } else if let Some(fingerprint) = cmd.get(EDIT_TAG_TABLE) {
+            ctx.new_window(tag_table::make_tag_table_window(data, fingerprint.clone()));
+            Handled::Yes
+        } else if let Some((fingerprint, rows)) = cmd.get(SAVE_TAG_TABLE) {
+            data.set_tag_table(fingerprint, rows);
+            // the defaults are saved along with the rest of the preferences
+            if let Some(fp) = fingerprint {
+                if let Some(path_buf) = data.save_document_info(fp) {
+                    if let Some(s) = path_buf.to_str() {
+                        data.just_saved.insert(s.to_string());
+                    }
+                }
+            }
+            self.show_pending_prompts(ctx, data);
+            Handled::Yes
+        } else if let Some((conflict, take_theirs)) = cmd.get(RESOLVE_SYNC_CONFLICT) {
+            data.resolve_sync_conflict(conflict, *take_theirs);
+            if let Some(path_buf) = data.save_document_info(&conflict.fingerprint) {
+                if let Some(s) = path_buf.to_str() {
+                    data.just_saved.insert(s.to_string());
+                }
+            }
+            if data.sync_conflicts.is_empty() {
+                if let Some(id) = self.sync_conflicts_window {
+                    ctx.submit_command(sys_cmds::CLOSE_WINDOW.to(id));
+                }
+            }
             Handled::Yes
         } else {
             match cmd {
@@ -949,22 +2419,47 @@ impl AppDelegate<AppState> for Delegate {
         &mut self,
         _id: WindowId,
         _handle: WindowHandle,
-        _data: &mut AppState,
+        data: &mut AppState,
         _env: &Env,
-        _ctx: &mut DelegateCtx,
+        ctx: &mut DelegateCtx,
     ) {
         self.window_count += 1;
+
+        // conflicts and warnings found while starting up can only be shown once there's a window
+        self.show_pending_prompts(ctx, data);
     }
 
     fn window_removed(
         &mut self,
-        _id: WindowId,
+        id: WindowId,
         data: &mut AppState,
         _env: &Env,
         _ctx: &mut DelegateCtx,
     ) {
         self.window_count -= 1;
 
+        if self.sync_conflicts_window == Some(id) {
+            self.sync_conflicts_window = None;
+        }
+        if self.fingerprint_collisions_window == Some(id) {
+            self.fingerprint_collisions_window = None;
+        }
+        if self.missing_books_window == Some(id) {
+            self.missing_books_window = None;
+        }
+        if self.warnings_window == Some(id) {
+            self.warnings_window = None;
+        }
+        if self.review_window == Some(id) {
+            self.review_window = None;
+            data.review_queue.clear();
+        }
+        if self.library_search_window == Some(id) {
+            self.library_search_window = None;
+            library_search::cancel_search();
+        }
+        data.pdf_view_windows.remove(&id);
+
         if self.window_count == 0 {
             data.save_all_doc_data();
             druid::Application::global().quit();
@@ -1013,6 +2508,12 @@ fn listen_for_messages(event_sink: druid::ExtEventSink) {
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 
 fn main() -> Result<(), PlatformError> {
+    let args: Vec<String> = env::args().collect();
+    if let Some(format) = args.get(1).and_then(|flag| export::ExportFormat::from_flag(flag)) {
+        export::export_from_command_line(format, &args[2..]);
+        return Ok(());
+    }
+
     let conn = LocalSocketStream::connect(IPC_CONNECTION_NAME);
 
     match conn {
@@ -1038,6 +2539,7 @@ fn main() -> Result<(), PlatformError> {
         }
         Err(_) => {
             let mut state = AppState::new();
+            state.fold_in_conflicted_copies();
 
             let mut args: Vec<String> = env::args().collect();
 
@@ -1054,17 +2556,20 @@ fn main() -> Result<(), PlatformError> {
                 .collect();
 
             let launcher;
+            let windows_to_open;
             if state.loaded_documents.is_empty() {
                 launcher = AppLauncher::with_window(
                     crate::book_info_window::make_book_info_window(&state, 0),
                 )
-                .configure_env(|env, _| env.set(druid::theme::UI_FONT, FontDescriptor::new(FontFamily::SANS_SERIF).with_size(16.)))
-                .delegate(Delegate::new(vec![]));
+                .configure_env(|env, _| env.set(druid::theme::UI_FONT, FontDescriptor::new(FontFamily::SANS_SERIF).with_size(16.)));
+                windows_to_open = vec![];
             } else {
                 launcher = AppLauncher::with_window(make_pdf_view_window(&mut state, 0, None))
-                    .configure_env(|env, _| env.set(druid::theme::UI_FONT, FontDescriptor::new(FontFamily::SANS_SERIF).with_size(16.)))
-                    .delegate(Delegate::new(windows[1..].to_vec()));
+                    .configure_env(|env, _| env.set(druid::theme::UI_FONT, FontDescriptor::new(FontFamily::SANS_SERIF).with_size(16.)));
+                windows_to_open = windows[1..].to_vec();
             }
+            let delegate_event_sink = launcher.get_external_handle();
+            let launcher = launcher.delegate(Delegate::new(windows_to_open, delegate_event_sink));
 
             let message_event_sink = launcher.get_external_handle();
             std::thread::spawn(move || listen_for_messages(message_event_sink));
@@ -1072,20 +2577,41 @@ fn main() -> Result<(), PlatformError> {
             //                if let Some(dir) = state.syncable_data_directory.clone() {
             //if let Some(dir) = state.preferences.syncable_data_directory.clone() {
             let file_change_notifications_event_sink = launcher.get_external_handle();
+            let library_changes = library::start_change_debouncer(launcher.get_external_handle());
+            // compared canonically, the events' paths can come from a library folder's watch rather than this directory's,
+            // and the preferences may reach it through a symlink
+            let syncable_directory = PathBuf::from(&state.preferences.syncable_data_directory);
+            let syncable_directory = fs::canonicalize(&syncable_directory).unwrap_or(syncable_directory);
 
             let watcher: Result<RecommendedWatcher, notify::Error> =
                 Watcher::new(move |res: Result<notify::event::Event, _>| {
                     if let Ok(event) = res {
                         // https://docs.rs/notify/5.0.0-pre.6/notify/event/enum.EventKind.html
                         println!("umm {:?}", event.kind);
-                        if let notify::EventKind::Modify(_)
+                        // new files matter too, sync clients create "conflicted copy" files rather than modifying existing ones
+                        if let notify::EventKind::Modify(_) | notify::EventKind::Create(_)
                             // notify::event::AccessKind::Data(
                             // notify::event::AccessMode::Write,
                         // )) 
                 = event.kind {
                             for path_buf in event.paths {
+                                // the library folders are watched too, and one of them could hold the syncable directory
+                                let selector = if library::is_pdf(&path_buf) {
+                                    if let Err(e) = library_changes.send(path_buf) {
+                                        println!("error sending file change notification: {}", e);
+                                    }
+                                    continue;
+                                } else if path_buf
+                                    .parent()
+                                    .and_then(|parent| fs::canonicalize(parent).ok())
+                                    .map_or(false, |parent| parent == syncable_directory)
+                                {
+                                    SYNCABLE_DIRECTORY_FILES_CHANGED
+                                } else {
+                                    continue;
+                                };
                                 if let Err(e) = file_change_notifications_event_sink.submit_command(
-                                    SYNCABLE_DIRECTORY_FILES_CHANGED,
+                                    selector,
                                     Box::<PathBuf>::new(path_buf),
                                     Target::Auto,
                                 ) {
@@ -1102,10 +2628,12 @@ fn main() -> Result<(), PlatformError> {
                         println!("problem trying to watch syncable data directory: {}", e);
                     }
 
-                    state.filesystem_watcher = Some(Arc::new(w));
+                    state.filesystem_watcher = Some(Arc::new(Mutex::new(w)));
                 }
                 Err(e) => println!("error making filesystem watcher: {}", e),
             }
+            state.watch_library_folders();
+            state.scan_library(launcher.get_external_handle());
 
             // let (mut doc_info, _) = DocumentInfo::from_fingerprint(&PathBuf::from(&self.preferences
             //                                                        .syncable_data_directory),
@@ -0,0 +1,157 @@
+use druid::kurbo::BezPath;
+use druid::widget::prelude::*;
+use druid::{
+    commands as sys_cmds, Color, Lens, LocalizedString, Point, Rect, Selector, WidgetExt,
+    WindowDesc,
+};
+
+use druid::widget::{Button, Flex, Label, LineBreaking, Scope, ScopeTransfer, TextBox};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, Fingerprint, PageNum};
+
+pub const EDIT_NOTE: Selector<NoteEdit> = Selector::new("edit-note");
+// saving a note with no text removes it
+pub const SAVE_NOTE: Selector<(NoteEdit, String)> = Selector::new("save-note");
+
+pub const NOTE_COLOR: Color = Color::rgb8(80, 200, 220);
+
+#[derive(Clone, Debug, Data, Serialize, Deserialize, PartialEq)]
+pub struct Note {
+    // part of the page the note is about, in page-normalised coordinates, or None for the whole page
+    pub region: Option<Rect>,
+    pub text: String,
+}
+
+impl Note {
+    pub fn applies_at(&self, p: Point) -> bool {
+        match self.region {
+            Some(r) => r.contains(p),
+            None => true,
+        }
+    }
+
+    // first line, shortened to fit in a menu
+    pub fn preview(&self) -> String {
+        let first_line = self.text.lines().next().unwrap_or("");
+        if first_line.chars().count() > 40 {
+            format!("{}...", first_line.chars().take(40).collect::<String>())
+        } else {
+            first_line.to_string()
+        }
+    }
+}
+
+// which note the editor window is working on, `idx` is None for a new one
+#[derive(Clone, Debug, Data, PartialEq)]
+pub struct NoteEdit {
+    pub fingerprint: Fingerprint,
+    pub page: PageNum,
+    pub idx: Option<usize>,
+    pub region: Option<Rect>,
+}
+
+// a folded-over page corner, drawn on pages and in the overview panel wherever there's a note
+pub fn note_marker(corner: Point, size: f64) -> BezPath {
+    let mut path = BezPath::new();
+    path.move_to((corner.x - size, corner.y));
+    path.line_to(corner);
+    path.line_to((corner.x, corner.y + size));
+    path.close_path();
+    path
+}
+
+#[derive(Clone, Data, Lens)]
+struct NoteEditor {
+    edit: NoteEdit,
+    text: String,
+}
+
+// the editor keeps its own copy of the text until "Save" is clicked
+struct NoteEditorTransfer;
+
+impl ScopeTransfer for NoteEditorTransfer {
+    type In = AppState;
+    type State = NoteEditor;
+
+    fn read_input(&self, _my_state: &mut Self::State, _external: &Self::In) {}
+
+    fn write_back_input(&self, _my_state: &Self::State, _external: &mut Self::In) {}
+}
+
+pub fn make_note_editor_window(data: &AppState, edit: NoteEdit) -> WindowDesc<AppState> {
+    let (description, text) = match data.all_local_documents_info.get(&edit.fingerprint) {
+        Some(info) => (
+            info.description.clone(),
+            edit.idx
+                .and_then(|idx| info.notes.get(&edit.page)?.get(idx).cloned())
+                .map(|note| note.text)
+                .unwrap_or_default(),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    let heading = format!(
+        "{} on page {} of \"{}\"",
+        if edit.region.is_some() {
+            "Note about a passage"
+        } else {
+            "Note"
+        },
+        edit.page + 1,
+        description
+    );
+
+    let ui = Scope::from_function(
+        move |_app_state: AppState| NoteEditor {
+            edit: edit.clone(),
+            text: text.clone(),
+        },
+        NoteEditorTransfer,
+        Flex::column()
+            .with_child(
+                Label::new(heading)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(10.0)
+                    .expand_width(),
+            )
+            .with_flex_child(
+                TextBox::multiline()
+                    .with_placeholder("Note text")
+                    .lens(NoteEditor::text)
+                    .expand()
+                    .padding(10.0),
+                1.,
+            )
+            .with_child(
+                Flex::row()
+                    .with_child(
+                        Button::new("Save").on_click(|ctx, data: &mut NoteEditor, _env| {
+                            ctx.submit_command(
+                                SAVE_NOTE.with((data.edit.clone(), data.text.clone())),
+                            );
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        }),
+                    )
+                    .with_default_spacer()
+                    .with_child(Button::new("Delete").on_click(
+                        |ctx, data: &mut NoteEditor, _env| {
+                            ctx.submit_command(SAVE_NOTE.with((data.edit.clone(), String::new())));
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        },
+                    ))
+                    .with_default_spacer()
+                    .with_child(Button::new("Cancel").on_click(
+                        |ctx, _data: &mut NoteEditor, _env| {
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        },
+                    ))
+                    .padding(10.0),
+            ),
+    );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Note"))
+        .window_size((450., 300.))
+}
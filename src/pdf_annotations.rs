@@ -0,0 +1,325 @@
+use druid::im::{HashMap, Vector};
+use druid::{ExtEventSink, Point, Rect, Selector, Target};
+
+use mupdf::pdf::{PdfDocument, PdfObject};
+use mupdf::Page;
+
+use crate::highlights::{page_chars, text_within, Highlight, PageChar, DEFAULT_HIGHLIGHT_COLOR};
+use crate::notes::Note;
+use crate::tag_table::{TagDef, FIRST_TAG};
+use crate::{Fingerprint, PageNum};
+
+// from a view window that's just opened on a book whose PDF annotations haven't been looked at yet
+pub const IMPORT_PDF_ANNOTATIONS: Selector<Fingerprint> = Selector::new("import-pdf-annotations");
+// from the importing thread
+pub const PDF_ANNOTATIONS_IMPORTED: Selector<(Fingerprint, ImportedAnnotations)> =
+    Selector::new("pdf-annotations-imported");
+
+// a page's boxes and rotation can be set on any of the page tree nodes above it, this far up at most
+pub const PAGE_TREE_DEPTH: usize = 32;
+
+// what was found in the PDF's own annotations, to be added to the book's info back on the UI thread
+#[derive(Default)]
+pub struct ImportedAnnotations {
+    pub highlights: HashMap<PageNum, Vector<Highlight>>,
+    pub notes: HashMap<PageNum, Vector<Note>>,
+    pub count: usize,
+}
+
+fn numbers(array: &PdfObject) -> Vec<f64> {
+    let len = array.len().unwrap_or(0);
+    (0..len)
+        .filter_map(|i| array.get_array(i as i32).ok()?)
+        .filter_map(|n| n.as_float().ok())
+        .map(|n| n as f64)
+        .collect()
+}
+
+fn string_entry(annot: &PdfObject, key: &str) -> Option<String> {
+    let value = annot.get_dict(key).ok()??;
+    let s = value.as_string().ok()?;
+    if s.trim().is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+// the tag colour closest to the annotation's own colour
+fn nearest_tag_color(annot: &PdfObject, tags: &Vector<TagDef>) -> usize {
+    let rgb = match annot.get_dict("C") {
+        Ok(Some(c)) => numbers(&c),
+        _ => Vec::new(),
+    };
+    if rgb.len() != 3 {
+        return DEFAULT_HIGHLIGHT_COLOR;
+    }
+
+    let distance = |tag: &usize| {
+        let (r, g, b, _) = tags[*tag].color().as_rgba8();
+        let dr = r as f64 / 255. - rgb[0];
+        let dg = g as f64 / 255. - rgb[1];
+        let db = b as f64 / 255. - rgb[2];
+        dr * dr + dg * dg + db * db
+    };
+
+    (FIRST_TAG..tags.len())
+        .min_by(|a, b| {
+            distance(a)
+                .partial_cmp(&distance(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap_or(DEFAULT_HIGHLIGHT_COLOR)
+}
+
+// PDF coordinates start at the bottom left of the page, ours at the top left of what's shown, which like mupdf's
+// page bounds that everything else is measured against, is the crop box turned clockwise by the page's /Rotate
+pub struct PageSpace {
+    crop_box: Rect,
+    quarter_turns: u32,
+}
+
+impl PageSpace {
+    pub fn from_page(page_obj: &PdfObject, page: &Page) -> Option<Self> {
+        let bounds = page.bounds().ok()?;
+        let fallback = Rect::new(0., 0., bounds.width() as f64, bounds.height() as f64);
+        let media_box = page_box(page_obj, "MediaBox").unwrap_or(fallback);
+        // mupdf shows only the part of the crop box that's inside the media box
+        let crop_box = page_box(page_obj, "CropBox")
+            .map(|crop_box| crop_box.intersect(media_box))
+            .filter(|crop_box| crop_box.area() > 0.)
+            .unwrap_or(media_box);
+        let rotate = inherited(page_obj, "Rotate", PAGE_TREE_DEPTH)
+            .and_then(|rotate| rotate.as_int().ok())
+            .unwrap_or(0);
+        Some(PageSpace {
+            crop_box,
+            quarter_turns: ((rotate / 90) % 4 + 4) as u32 % 4,
+        })
+    }
+
+    pub fn normalize(&self, x: f64, y: f64) -> Point {
+        let cb = self.crop_box;
+        let u = (x - cb.x0) / cb.width();
+        let v = (cb.y1 - y) / cb.height();
+        match self.quarter_turns {
+            1 => Point::new(1. - v, u),
+            2 => Point::new(1. - u, 1. - v),
+            3 => Point::new(v, 1. - u),
+            _ => Point::new(u, v),
+        }
+    }
+
+    // back to PDF coordinates
+    fn denormalize(&self, p: Point) -> Point {
+        let cb = self.crop_box;
+        let (u, v) = match self.quarter_turns {
+            1 => (p.y, 1. - p.x),
+            2 => (1. - p.x, 1. - p.y),
+            3 => (1. - p.y, p.x),
+            _ => (p.x, p.y),
+        };
+        Point::new(cb.x0 + u * cb.width(), cb.y1 - v * cb.height())
+    }
+
+    // bounding boxes of each group of four corners, as used by /QuadPoints, or of the two corners in a /Rect
+    fn rects(&self, coords: &[f64], points_per_rect: usize) -> Vector<Rect> {
+        coords
+            .chunks_exact(points_per_rect * 2)
+            .map(|corners| {
+                let mut points = corners
+                    .chunks_exact(2)
+                    .map(|xy| self.normalize(xy[0], xy[1]));
+                let first = points.next().unwrap_or(Point::ZERO);
+                points.fold(Rect::from_points(first, first), |acc, p| acc.union_pt(p))
+            })
+            .collect()
+    }
+
+    // back to PDF coordinates, as [x0, y0, x1, y1] with y0 at the bottom
+    pub fn denormalize_rect(&self, r: &Rect) -> [f64; 4] {
+        let pdf_rect = Rect::from_points(
+            self.denormalize(Point::new(r.x0, r.y0)),
+            self.denormalize(Point::new(r.x1, r.y1)),
+        );
+        [pdf_rect.x0, pdf_rect.y0, pdf_rect.x1, pdf_rect.y1]
+    }
+
+    // corners in the top left, top right, bottom left, bottom right order /QuadPoints uses, as the text reads
+    // on the page as shown, which for a rotated page isn't the same as in PDF coordinates
+    pub fn quad_points(&self, r: &Rect) -> [f64; 8] {
+        let mut coords = [0.; 8];
+        let corners = [
+            Point::new(r.x0, r.y0),
+            Point::new(r.x1, r.y0),
+            Point::new(r.x0, r.y1),
+            Point::new(r.x1, r.y1),
+        ];
+        for (i, corner) in corners.iter().enumerate() {
+            let p = self.denormalize(*corner);
+            coords[i * 2] = p.x;
+            coords[i * 2 + 1] = p.y;
+        }
+        coords
+    }
+}
+
+pub fn inherited(node: &PdfObject, key: &str, depth: usize) -> Option<PdfObject> {
+    if let Ok(Some(value)) = node.get_dict(key) {
+        return Some(value);
+    }
+    if depth == 0 {
+        return None;
+    }
+    let parent = node.get_dict("Parent").ok()??;
+    inherited(&parent, key, depth - 1)
+}
+
+fn page_box(page_obj: &PdfObject, key: &str) -> Option<Rect> {
+    let n = numbers(&inherited(page_obj, key, PAGE_TREE_DEPTH)?);
+    if n.len() == 4 && n[2] != n[0] && n[3] != n[1] {
+        Some(Rect::new(n[0], n[1], n[2], n[3]).abs())
+    } else {
+        None
+    }
+}
+
+// reads highlights and comments made in other PDF readers, for the book's own info, leaving the PDF file untouched
+// `tags` is the book's tag table, whose colours the imported highlights are matched to
+fn import_pdf_annotations(pdf_doc: &PdfDocument, tags: &Vector<TagDef>) -> ImportedAnnotations {
+    let mut imported = ImportedAnnotations::default();
+
+    let page_count = pdf_doc.page_count().unwrap_or(0);
+    for page_number in 0..page_count {
+        let page_obj = match pdf_doc.find_page(page_number) {
+            Ok(obj) => obj,
+            Err(e) => {
+                println!("Unable to read page {} for annotations: {}", page_number, e);
+                continue;
+            }
+        };
+        let annots = match page_obj.get_dict("Annots") {
+            Ok(Some(annots)) => annots,
+            _ => continue,
+        };
+        let page = match pdf_doc.load_page(page_number) {
+            Ok(page) => page,
+            Err(e) => {
+                println!("Unable to load page {} for annotations: {}", page_number, e);
+                continue;
+            }
+        };
+
+        let space = match PageSpace::from_page(&page_obj, &page) {
+            Some(space) => space,
+            None => continue,
+        };
+        let page_num = page_number as PageNum;
+
+        // only extracted if there turn out to be highlights on the page
+        let mut chars: Option<Vec<PageChar>> = None;
+
+        for i in 0..annots.len().unwrap_or(0) {
+            let annot = match annots.get_array(i as i32) {
+                Ok(Some(annot)) => annot,
+                _ => continue,
+            };
+            let subtype = match annot.get_dict("Subtype") {
+                Ok(Some(s)) => s.as_name().map(|n| n.to_vec()).unwrap_or_default(),
+                _ => continue,
+            };
+            let contents = string_entry(&annot, "Contents");
+            let rect = match annot.get_dict("Rect") {
+                Ok(Some(r)) => space.rects(&numbers(&r), 2).front().copied(),
+                _ => None,
+            };
+
+            match subtype.as_slice() {
+                b"Highlight" | b"Underline" | b"Squiggly" | b"StrikeOut" => {
+                    let mut quads = match annot.get_dict("QuadPoints") {
+                        Ok(Some(q)) => space.rects(&numbers(&q), 4),
+                        _ => Vector::new(),
+                    };
+                    if quads.is_empty() {
+                        if let Some(r) = rect {
+                            quads.push_back(r);
+                        } else {
+                            continue;
+                        }
+                    }
+
+                    let chars = chars.get_or_insert_with(|| page_chars(&page));
+                    let highlight = Highlight {
+                        text: text_within(chars, &quads),
+                        quads,
+                        color: nearest_tag_color(&annot, tags),
+                    };
+
+                    // a comment attached to the highlight becomes a note about the same passage
+                    if let Some(text) = contents {
+                        imported
+                            .notes
+                            .entry(page_num)
+                            .or_insert_with(Vector::<Note>::new)
+                            .push_back(Note {
+                                region: Some(highlight.bounds()),
+                                text,
+                            });
+                    }
+                    imported
+                        .highlights
+                        .entry(page_num)
+                        .or_insert_with(Vector::<Highlight>::new)
+                        .push_back(highlight);
+                    imported.count += 1;
+                }
+                b"Text" | b"FreeText" | b"Ink" => {
+                    let text = match (contents, subtype.as_slice()) {
+                        (Some(text), _) => text,
+                        (None, b"Ink") => "(ink drawing)".to_string(),
+                        (None, _) => continue,
+                    };
+                    imported
+                        .notes
+                        .entry(page_num)
+                        .or_insert_with(Vector::<Note>::new)
+                        .push_back(Note { region: rect, text });
+                    imported.count += 1;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    imported
+}
+
+// going through every page of a big book takes a while, so it's done away from the UI thread, like the text index
+// `filepath` is opened again on the importing thread, as an open document can't be sent between threads
+pub fn start_import(
+    sink: ExtEventSink,
+    fingerprint: Fingerprint,
+    filepath: String,
+    tags: Vector<TagDef>,
+) {
+    std::thread::spawn(move || {
+        let pdf_doc = match PdfDocument::open(&filepath) {
+            Ok(pdf_doc) => pdf_doc,
+            Err(e) => {
+                println!(
+                    "Error opening {} to import its annotations: {}",
+                    filepath, e
+                );
+                return;
+            }
+        };
+        let imported = import_pdf_annotations(&pdf_doc, &tags);
+        if let Err(e) = sink.submit_command(
+            PDF_ANNOTATIONS_IMPORTED,
+            Box::new((fingerprint, imported)),
+            Target::Auto,
+        ) {
+            println!("error sending imported annotations: {}", e);
+        }
+    });
+}
@@ -30,6 +30,10 @@ use crate::pdf_view::TOGGLE_CROP_MODE;
 
 use crate::pdf_view::START_INVERSION_AREA_SELECTION;
 
+use crate::highlights::{page_chars, select_between, Highlight, PageChar};
+use crate::notes::{note_marker, NOTE_COLOR};
+use crate::tag_table::tag_table;
+
 use crate::PageNum;
 use crate::UNIT_SQUARE;
 
@@ -98,6 +102,9 @@ pub struct PdfTextWidget {
     data_update: bool,
 
     inversion_rect_edit_pixmap: Option<Pixmap>,
+
+    selection_chars: Vec<PageChar>,
+    text_selection: Option<(PageNum, Highlight)>,
 }
 
 impl PdfTextWidget {
@@ -115,6 +122,9 @@ impl PdfTextWidget {
             data_update: true,
 
             inversion_rect_edit_pixmap: None,
+
+            selection_chars: Vec::<PageChar>::new(),
+            text_selection: None,
         }
     }
 
@@ -523,6 +533,11 @@ const CROP_HANDLE_SIZE: f64 = 1. / 3.;
 
 const INVERSION_AREA_HANDLE_SIZE: f64 = 20.;
 
+const NOTE_MARKER_SIZE: f64 = 16.;
+
+// the search hit chosen in the results panel, the others are outlined in orange
+const CURRENT_HIT_COLOR: Color = Color::rgb8(230, 40, 90);
+
 fn min_max_keys<T>(map: &BTreeMap<PageNum, T>) -> (PageNum, PageNum) {
     let mut min = 100000000;
     let mut max = 0;
@@ -626,7 +641,8 @@ impl Widget<PdfViewState> for PdfTextWidget {
             Event::MouseDown(e) => {
                 if e.button.is_right() {
                     let (page_number, _) = self.hover_target;
-                    let menu = make_context_menu(data, page_number);
+                    let pos = self.page_coords_of_screen_point(data, page_number, e.pos);
+                    let menu = make_context_menu(data, page_number, pos);
                     ctx.show_context_menu(menu, e.pos);
                     data.ignore_next_mouse_move = true;
                 }
@@ -668,6 +684,12 @@ impl Widget<PdfViewState> for PdfTextWidget {
                             mouse_corner,
                             other_corner,
                         );
+                    } else if e.mods.shift() && data.in_reading_mode() {
+                        // shift-drag highlights text
+                        let start = self.page_coords_of_screen_point(data, curr_page, e.pos);
+                        self.selection_chars = page_chars(&data.document.load_page(curr_page));
+                        data.mouse_state = MouseState::TextSelection(curr_page, start);
+                        ctx.set_active(true);
                     } else if data.crop_weight == 0. {
                         let start_crop_rect = data
                             .document_info
@@ -719,6 +741,17 @@ impl Widget<PdfViewState> for PdfTextWidget {
                             mouse_offset,
                             other_corner,
                         );
+                    } else if let MouseState::TextSelection(..) = data.mouse_state {
+                        if let Some((page_number, highlight)) = self.text_selection.take() {
+                            data.document.doc_info_changed = true;
+                            data.document_info
+                                .highlights
+                                .entry(page_number)
+                                .or_insert_with(Vector::<Highlight>::new)
+                                .push_back(highlight);
+                        }
+                        self.selection_chars.clear();
+                        ctx.request_paint();
                     }
                 }
                 data.mouse_state = MouseState::Undragged;
@@ -774,6 +807,17 @@ impl Widget<PdfViewState> for PdfTextWidget {
                             other_corner,
                         )
                     }
+                    MouseState::TextSelection(page_number, start) => {
+                        let end = self.page_coords_of_screen_point(data, page_number, e.pos);
+                        self.text_selection = select_between(
+                            &self.selection_chars,
+                            start,
+                            end,
+                            data.document_info.highlight_color(page_number),
+                        )
+                        .map(|highlight| (page_number, highlight));
+                        ctx.request_paint();
+                    }
                 }
             }
 
@@ -1114,6 +1158,42 @@ impl Widget<PdfViewState> for PdfTextWidget {
                     layout.draw(ctx, (x0, y0 - 30.));
                 }
 
+                // highlights, plus the one being dragged out
+                let scale = |r: &Rect| Rect {
+                    x0: r.x0 * image_size.width,
+                    x1: r.x1 * image_size.width,
+                    y0: r.y0 * image_size.height,
+                    y1: r.y1 * image_size.height,
+                };
+                let selecting = self
+                    .text_selection
+                    .iter()
+                    .filter(|(page, _)| page == page_number)
+                    .map(|(_, highlight)| highlight);
+                let saved = data.document_info.highlights.get(page_number).into_iter().flatten();
+                let tags = tag_table(&data.document_info, &data.preferences.default_tag_table);
+                for h in saved.chain(selecting) {
+                    let color = tags[h.color % tags.len()].color().with_alpha(0.35);
+                    for r in &h.quads {
+                        ctx.fill(scale(r), &color);
+                    }
+                }
+
+                // notes about a passage outline it, notes about the whole page go in the corner of the crop area
+                if let Some(notes) = data.document_info.notes.get(page_number) {
+                    for note in notes {
+                        let corner = match note.region {
+                            Some(region) => {
+                                let r = scale(&region);
+                                ctx.stroke(r, &NOTE_COLOR, 2.);
+                                Point::new(r.x1, r.y0)
+                            }
+                            None => Point::new(x1, y0),
+                        };
+                        ctx.fill(note_marker(corner, NOTE_MARKER_SIZE), &NOTE_COLOR);
+                    }
+                }
+
                 // outline search results
                 let results = data.search_results.borrow();
                 if let Some(rects) = data.search_results.borrow().get(page_number) {
@@ -1130,6 +1210,16 @@ impl Widget<PdfViewState> for PdfTextWidget {
                         );
                     }
                 }
+                // the one picked in the results panel stands out from the rest
+                if let Some(hit) = data.current_search_hit() {
+                    if hit.page == *page_number {
+                        for r in &hit.rects {
+                            let r = scale(r).inflate(2., 2.);
+                            ctx.fill(r, &CURRENT_HIT_COLOR.with_alpha(0.3));
+                            ctx.stroke(r, &CURRENT_HIT_COLOR, 3.);
+                        }
+                    }
+                }
 
                 ctx.stroke(Rect { x0, y0, x1, y1 }, &color, 3.0);
 
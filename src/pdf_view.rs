@@ -3,9 +3,9 @@ use druid::kurbo::BezPath;
 use druid::piet::{FontFamily, ImageFormat, InterpolationMode, PietImage, Text, TextLayoutBuilder};
 use druid::widget::prelude::*;
 use druid::{
-    Affine, AppLauncher, Color, Command, FileDialogOptions, FileSpec, FontDescriptor, FontStyle,
-    FontWeight, Handled, Lens, LocalizedString, Menu, MenuItem, MouseButton, MouseEvent, Point,
-    Rect, Selector, SysMods, Target, TextLayout, Vec2, WindowDesc, WindowId,
+    Affine, AppLauncher, Color, Command, ExtEventSink, FileDialogOptions, FileSpec, FontDescriptor,
+    FontStyle, FontWeight, Handled, Lens, LocalizedString, Menu, MenuItem, MouseButton, MouseEvent,
+    Point, Rect, Selector, SysMods, Target, TextLayout, Vec2, WindowDesc, WindowId,
 };
 
 use druid::widget::{
@@ -24,6 +24,8 @@ use std::time::Instant;
 // use std::sync::Arc;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 use crate::preferences::{DoubleClickAction, Preferences, ScrollbarLayout};
 use crate::{Document, DocumentInfo};
@@ -45,6 +47,30 @@ pub const TOGGLE_EVEN_ODD_PAGE_DISTINCTION: Selector<PageNum> =
     Selector::new("toggle-even-odd-page-distinction");
 pub const REMOVE_COLOR_INVERSION_RECTANGLE: Selector<PageNum> =
     Selector::new("remove-color-inversion-rectangle");
+use crate::annotated_pdf::{write_annotated_pdf, EXPORT_ANNOTATED_PDF, PDF_FILE};
+use crate::export::{export_to_file, MARKDOWN_FILE, ORG_FILE};
+use crate::bookmarks::{
+    is_letter_shortcut, make_bookmarks_panel, ADD_NAMED_BOOKMARK, GO_TO_BOOKMARK,
+    REMOVE_BOOKMARK,
+};
+use crate::global_marks::{CLEAR_GLOBAL_MARKS, GLOBAL_MARK_KEY, SET_GLOBAL_MARK};
+use crate::reading_time::{
+    add_reading_time, ReadingClock, PAUSE_READING, READING_TICK, READING_WINDOW,
+    TOGGLE_READING_HEAT,
+};
+use crate::prerequisites::{EDIT_PREREQUISITES, SHOW_READING_ORDER};
+use crate::review::REVIEW_DUE_PAGES;
+use crate::library_search::SHOW_LIBRARY_SEARCH;
+use crate::text_index::INDEX_DOCUMENT;
+use crate::pdf_annotations::IMPORT_PDF_ANNOTATIONS;
+use crate::search_worker::{self, SearchBatch, SEARCH_BATCH};
+use crate::text_match::{FindOptions, Matcher, PageHit};
+use crate::search_results::{make_search_results_panel, SearchHit, GO_TO_SEARCH_HIT};
+use crate::tag_query::TagQuery;
+use crate::tag_table::{tag_table, EDIT_TAG_TABLE};
+use crate::notes::{NoteEdit, EDIT_NOTE};
+
+pub const REMOVE_HIGHLIGHT: Selector<(PageNum, usize)> = Selector::new("remove-highlight");
 pub const SHOW_BOOK_INFO: Selector<usize> = Selector::new("show-book-info");
 pub const SAVE_DOCUMENT_INFO: Selector<Fingerprint> = Selector::new("save-document-info");
 pub const REPOSITION_OVERVIEW: Selector = Selector::new("reposition-overview");
@@ -65,6 +91,7 @@ pub enum MouseState {
     },
     ScrollPageDrag(Point, PageNum, f64),
     ColourInversionRect(PageNum, Vec2, Point),
+    TextSelection(PageNum, Point), // where the drag started, in page coordinates
 }
 
 #[derive(Copy, Clone, Debug, Data, PartialEq)]
@@ -99,6 +126,8 @@ pub enum WindowMode {
     Normal,
     Goto,
     Find,
+    TagQuery,
+    Bookmarks,
     //Search(PageNum,String)
     // Search(String),
     // SearchActive(String, PageNum, PageNum)
@@ -149,9 +178,24 @@ pub struct PdfViewState {
     String
     ,//>,
     pub search_progress: Option<(PageNum, PageNum)>,
+    pub find_options: FindOptions,
+    // why the last search couldn't be started, such as a regular expression that doesn't parse
+    pub search_error: String,
     pub search_results: Rc<RefCell<BTreeMap<PageNum, Vec<Rect>>>>,
+    // the same hits in document order, with the text around them, for the results panel
+    pub search_hits: Vector<SearchHit>,
+    // set to stop the search thread, for books whose text index isn't ready yet
+    pub search_worker: Option<Arc<AtomicBool>>,
     pub goto_page: String,
     pub goto_offset: String,
+    // expression from the tag query bar, kept after the bar closes so PgUp/PgDown keep to the matching pages
+    pub tag_query_text: String,
+    // whether "Export annotated PDF" was asked to crop pages, remembered while the save dialog is open
+    pub crop_exported_pdf: bool,
+    // shade the overview panel's pages by how long they've been read
+    pub show_reading_heat: bool,
+    // ' was typed and the next letter names a global mark
+    pub global_mark_pending: bool,
 }
 
 impl PdfViewState {
@@ -192,10 +236,18 @@ impl PdfViewState {
 
             find_goal: String::new(),
             search_progress: None,
+            find_options: FindOptions::default(),
+            search_error: String::new(),
 
             search_results: Rc::<RefCell<BTreeMap<PageNum, Vec<Rect>>>>::default(),
+            search_hits: Vector::new(),
+            search_worker: None,
             goto_page: String::new(),
             goto_offset: String::new(),
+            tag_query_text: String::new(),
+            crop_exported_pdf: false,
+            show_reading_heat: false,
+            global_mark_pending: false,
         }
     }
 
@@ -216,8 +268,13 @@ impl PdfViewState {
             mouse_over_hyperlink: None,
             find_goal: old.find_goal.clone(),
             search_results: old.search_results.clone(),
+            search_hits: old.search_hits.clone(),
+            search_error: old.search_error.clone(),
+            // the search belongs to the old window, which is where its results are sent
+            search_worker: None,
             goto_page: old.goto_page.clone(),
             goto_offset: old.goto_offset.clone(),
+            tag_query_text: old.tag_query_text.clone(),
 
             ..*old
         }
@@ -728,7 +785,7 @@ impl PdfViewState {
         }
     }
 
-    fn page_number_or_link_target(&self) -> PageNum {
+    pub fn page_number_or_link_target(&self) -> PageNum {
         if let Some((hyperlink_to_page_number, _)) = self.mouse_over_hyperlink {
             if hyperlink_to_page_number > 0 {
                 return hyperlink_to_page_number;
@@ -737,6 +794,43 @@ impl PdfViewState {
         self.page_number
     }
 
+    // None when the query bar is empty or its expression doesn't parse
+    pub fn tag_query(&self) -> Option<TagQuery> {
+        let tags = tag_table(&self.document_info, &self.preferences.default_tag_table);
+        TagQuery::parse(&self.tag_query_text, &tags).ok().flatten()
+    }
+
+    pub fn tag_query_status(&self) -> String {
+        let tags = tag_table(&self.document_info, &self.preferences.default_tag_table);
+        match TagQuery::parse(&self.tag_query_text, &tags) {
+            Ok(Some(query)) => {
+                let count = (0..self.document_info.page_count)
+                    .filter(|page| query.matches(self.document_info.tag_bits(*page)))
+                    .count();
+                format!("{} matching pages, PgUp / PgDown to step through them", count)
+            }
+            Ok(None) => "e.g. 3 & !5, 2 | 4, or tag names: red & (blue | green)".to_string(),
+            Err(e) => e,
+        }
+    }
+
+    // with no tag query, any page with tags counts
+    pub fn next_tagged_page(&self, from: PageNum, forward: bool) -> Option<PageNum> {
+        let query = self.tag_query();
+        let matches = |page: &PageNum| {
+            let bits = self.document_info.tag_bits(*page);
+            match &query {
+                Some(q) => q.matches(bits),
+                None => bits != 0,
+            }
+        };
+        if forward {
+            (from + 1..self.document_info.page_count).find(matches)
+        } else {
+            (0..from).rev().find(matches)
+        }
+    }
+
     pub fn show_page(&mut self, window_id: WindowId, page_number: PageNum) {
         let page_number = PageNum::min(page_number, self.document_info.page_count - 1);
         if self.page_number == self.overview_selected_page {
@@ -838,26 +932,171 @@ impl PdfViewState {
         }
     }
 
-    pub fn search_page(&mut self, page_num: PageNum) {
-        let page = self.document.load_page(page_num);
-        let mut results = self.search_results.borrow_mut();
-        let entry = results.entry(page_num).or_insert_with(Vec::<Rect>::new);
-
-        let size = page.bounds().expect("Unable to get page bounds");
-        let w = size.width() as f64;
-        let h = size.height() as f64;
-
-        let finds = page.search(&self.find_goal, 10);
-        if let Ok(finds) = finds {
-            for f in finds {
-                // println!("found {:?}", f);
-                entry.push(Rect {
-                    x0: f.ul.x as f64 / w,
-                    y0: f.ul.y as f64 / h,
-                    x1: f.lr.x as f64 / w,
-                    y1: f.lr.y as f64 / h,
-                });
+    // with the book's text index every page is searched at once, false if it hasn't arrived yet
+    pub fn search_all_pages(&mut self, matcher: &Matcher) -> bool {
+        let index = match &self.document.text_index {
+            Some(index) => index.clone(),
+            None => return false,
+        };
+        for (page, hits) in index.search(matcher) {
+            self.add_page_hits(page, &hits);
+        }
+        self.search_progress = Some((0, self.document_info.page_count.saturating_sub(1)));
+        true
+    }
+
+    // a new search replaces whatever one's in progress, an empty one just stops it
+    pub fn start_search(&mut self, sink: ExtEventSink, window_id: WindowId) {
+        self.stop_search();
+        if self.find_goal.trim().is_empty() {
+            return;
+        }
+        let matcher = match Matcher::new(&self.find_goal, self.find_options) {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.search_error = e;
+                return;
             }
+        };
+        if self.search_all_pages(&matcher) {
+            return;
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.search_worker = Some(cancelled.clone());
+        search_worker::start_search(
+            sink,
+            window_id,
+            self.document.filepath.clone(),
+            matcher,
+            self.page_number,
+            cancelled,
+        );
+    }
+
+    pub fn stop_search(&mut self) {
+        search_worker::cancel(&self.search_worker);
+        self.search_worker = None;
+        self.search_results.borrow_mut().clear();
+        self.search_hits.clear();
+        self.search_progress = None;
+        self.search_error.clear();
+    }
+
+    pub fn add_search_batch(&mut self, batch: &SearchBatch) {
+        // from a search that's since been replaced
+        match &self.search_worker {
+            Some(current) if Arc::ptr_eq(current, &batch.cancelled) => {}
+            _ => return,
+        }
+        for (page, hits) in &batch.hits {
+            self.add_page_hits(*page, hits);
+        }
+        // the worker goes outwards from where the reader was, the panel lists them from the start
+        self.search_hits
+            .sort_by(|a, b| (a.page, a.nth).cmp(&(b.page, b.nth)));
+        self.search_progress = Some((batch.first, batch.last));
+        if batch.first == 0 && batch.last + 1 >= self.document_info.page_count {
+            self.search_worker = None;
+        }
+    }
+
+    fn add_page_hits(&mut self, page: PageNum, hits: &[PageHit]) {
+        let chapters = self.document.chapters.clone();
+        for (nth, hit) in hits.iter().enumerate() {
+            self.search_hits.push_back(SearchHit::new(
+                page,
+                nth,
+                hit,
+                &chapters,
+                &self.document_info,
+            ));
+        }
+        self.search_results.borrow_mut().insert(
+            page,
+            hits.iter().flat_map(|hit| hit.rects.iter().cloned()).collect(),
+        );
+    }
+
+    pub fn current_search_hit(&self) -> Option<&SearchHit> {
+        self.search_hits.iter().find(|hit| hit.current)
+    }
+
+    // shows the page with the hit in the middle of the view
+    pub fn select_search_hit(&mut self, window_id: WindowId, idx: usize) {
+        let (page, bounds) = match self.search_hits.get(idx) {
+            Some(hit) => (hit.page, hit.bounds()),
+            None => return,
+        };
+        for (i, hit) in self.search_hits.iter_mut().enumerate() {
+            hit.current = i == idx;
+        }
+        self.show_page(window_id, page);
+        let (min, max) = self.scroll_direction.major_span(bounds);
+        self.page_position = (min + max) / 2.;
+    }
+
+    pub fn go_to_search_hit(&mut self, window_id: WindowId, page: PageNum, nth: usize) {
+        if let Some(idx) = self
+            .search_hits
+            .iter()
+            .position(|hit| hit.page == page && hit.nth == nth)
+        {
+            self.select_search_hit(window_id, idx);
+        }
+    }
+
+    // to the next or previous hit, going round from the end of the book to the start
+    // with none chosen yet, it's the first one from the current page on
+    pub fn step_search_hit(&mut self, window_id: WindowId, forward: bool) {
+        let count = self.search_hits.len();
+        if count == 0 {
+            return;
+        }
+        let idx = match self.search_hits.iter().position(|hit| hit.current) {
+            Some(idx) if forward => (idx + 1) % count,
+            Some(idx) => (idx + count - 1) % count,
+            None if forward => self
+                .search_hits
+                .iter()
+                .position(|hit| hit.page >= self.page_number)
+                .unwrap_or(0),
+            None => self
+                .search_hits
+                .iter()
+                .rposition(|hit| hit.page <= self.page_number)
+                .unwrap_or(count - 1),
+        };
+        self.select_search_hit(window_id, idx);
+    }
+
+    // for the find bar
+    pub fn search_status(&self) -> String {
+        if self.find_goal.trim().is_empty() {
+            return String::new();
+        }
+        if !self.search_error.is_empty() {
+            return self.search_error.clone();
+        }
+        let hits = self.search_hits.len();
+        let pages = self
+            .search_results
+            .borrow()
+            .values()
+            .filter(|rects| !rects.is_empty())
+            .count();
+        match self.search_progress {
+            Some((first, last)) if last + 1 - first < self.document_info.page_count => format!(
+                "searched {} of {} pages, {} hits so far",
+                last + 1 - first,
+                self.document_info.page_count,
+                hits
+            ),
+            Some(_) => match self.search_hits.iter().position(|hit| hit.current) {
+                Some(idx) => format!("hit {} of {}, on {} pages", idx + 1, hits, pages),
+                None => format!("{} hits on {} pages", hits, pages),
+            },
+            None => "searching...".to_string(),
         }
     }
 }
@@ -1012,10 +1251,12 @@ fn pdf_view_switcher(
             (Nowhere, _) => Box::new(PdfTextWidget::new()),
         },
     )
-    .controller(PdfWindowController)
+    .controller(PdfWindowController {
+        reading: ReadingClock::new(),
+    })
 }
 
-use crate::find_goto_controllers::{make_find_ui, make_goto_ui};
+use crate::find_goto_controllers::{make_find_ui, make_goto_ui, make_tag_query_ui};
 
 pub fn make_pdf_view_window(
     app_state: &mut AppState,
@@ -1048,9 +1289,24 @@ pub fn make_pdf_view_window(
                 ),
                 WindowMode::Find => Box::new(
                     Flex::column()
-                        .with_flex_child(pdf_view_switcher().expand(), 1.)
+                        .with_flex_child(
+                            Flex::row()
+                                .with_flex_child(pdf_view_switcher().expand(), 1.)
+                                .with_child(make_search_results_panel().expand_height()),
+                            1.,
+                        )
                         .with_child(make_find_ui()),
                 ),
+                WindowMode::TagQuery => Box::new(
+                    Flex::column()
+                        .with_child(make_tag_query_ui())
+                        .with_flex_child(pdf_view_switcher().expand(), 1.),
+                ),
+                WindowMode::Bookmarks => Box::new(
+                    Flex::row()
+                        .with_flex_child(pdf_view_switcher().expand(), 1.)
+                        .with_child(make_bookmarks_panel()),
+                ),
             },
         )),
     );
@@ -1066,6 +1322,8 @@ pub fn make_pdf_view_window(
         .get(&app_state.loaded_documents[doc_idx].fingerprint)
         .unwrap();
     let page = info.most_recent_page;
+    let fingerprint = info.fingerprint.clone();
+    app_state.pdf_view_windows.insert(win_id, fingerprint);
     // app_state.loaded_documents[doc_idx].current_page_number_in_window_id
     //                             .insert(win_id, page);
     app_state
@@ -1098,7 +1356,23 @@ pub fn make_pdf_view_window(
 
 use crate::CHECK_FOR_WINDOWS_TO_OPEN;
 
-struct PdfWindowController;
+struct PdfWindowController {
+    reading: ReadingClock,
+}
+
+impl PdfWindowController {
+    fn credit_reading(&mut self, data: &mut PdfViewState, pause: bool) {
+        let credited = if pause {
+            self.reading.pause(data.page_number)
+        } else {
+            self.reading.take(data.page_number)
+        };
+        if let Some((page, seconds)) = credited {
+            add_reading_time(&mut data.document_info, page, seconds);
+            data.document.doc_info_changed = true;
+        }
+    }
+}
 
 impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowController {
     fn event(
@@ -1109,12 +1383,47 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
         data: &mut PdfViewState,
         env: &Env,
     ) {
+        let reading_tick = matches!(event, Event::Timer(token) if *token == self.reading.timer);
+        if let Event::MouseMove(_) | Event::MouseDown(_) | Event::Wheel(_) | Event::KeyDown(_) =
+            event
+        {
+            if self.reading.input() {
+                ctx.submit_command(
+                    READING_WINDOW
+                        .with(ctx.window_id())
+                        .to(druid::Target::Global),
+                );
+            }
+        }
+
         match event {
+            Event::Timer(_) if reading_tick => {
+                self.reading.timer = ctx.request_timer(READING_TICK);
+            }
             Event::WindowConnected => {
+                if data.document.text_index.is_none() {
+                    ctx.submit_command(INDEX_DOCUMENT.with(data.document.fingerprint.clone()));
+                }
+                if !data.document_info.pdf_annotations_imported {
+                    ctx.submit_command(
+                        IMPORT_PDF_ANNOTATIONS.with(data.document.fingerprint.clone()),
+                    );
+                }
                 ctx.submit_command(CHECK_FOR_WINDOWS_TO_OPEN);
+                // a new window is the one being read
+                ctx.submit_command(
+                    READING_WINDOW
+                        .with(ctx.window_id())
+                        .to(druid::Target::Global),
+                );
                 ctx.request_focus();
                 ctx.set_handled();
             }
+            Event::WindowDisconnected => {
+                // the window has closed, so nothing is left to show the search's results
+                data.stop_search();
+                child.event(ctx, event, data, env)
+            }
             Event::Command(cmd) => {
                 //                } else
                 if cmd.is(REFRESH_PAGE_IMAGES) {
@@ -1122,6 +1431,27 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                     ctx.request_paint();
                 } else if cmd.is(REPOSITION_OVERVIEW) {
                     data.scrollbar_position = data.scrollbar_position.next();
+                } else if let Some(batch) = cmd.get(SEARCH_BATCH) {
+                    // here rather than in the overview panel, which ignores events while the mouse is dragging
+                    data.add_search_batch(batch);
+                } else if let Some((page, nth)) = cmd.get(GO_TO_SEARCH_HIT) {
+                    data.history.push_back(data.overview_selected_page);
+                    data.go_to_search_hit(ctx.window_id(), *page, *nth);
+                } else if cmd.is(TOGGLE_READING_HEAT) {
+                    data.show_reading_heat = !data.show_reading_heat;
+                } else if let Some(window_id) = cmd.get(READING_WINDOW) {
+                    // not handled, so the command carries on to the other windows
+                    if *window_id != ctx.window_id() {
+                        self.credit_reading(data, true);
+                    }
+                } else if cmd.is(PAUSE_READING) {
+                    self.credit_reading(data, true);
+                    if data.document.doc_info_changed {
+                        // save on focus lost
+                        ctx.submit_command(
+                            SAVE_DOCUMENT_INFO.with(data.document_info.fingerprint.clone()),
+                        );
+                    }
                 } else if cmd.is(NEW_VIEW) {
                     ctx.submit_command(
                         NEW_VIEW_WITH_PARENT
@@ -1142,11 +1472,58 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                         rects.pop_back();
                         data.page_image_cache.borrow_mut().remove(page_number);
                     }
+                } else if let Some((page_number, idx)) = cmd.get(REMOVE_HIGHLIGHT) {
+                    if let Some(highlights) = data.document_info.highlights.get_mut(page_number) {
+                        if *idx < highlights.len() {
+                            highlights.remove(*idx);
+                            data.document.doc_info_changed = true;
+                        }
+                        if highlights.is_empty() {
+                            data.document_info.highlights.remove(page_number);
+                        }
+                    }
+                } else if let Some(crop) = cmd.get(EXPORT_ANNOTATED_PDF) {
+                    data.crop_exported_pdf = *crop;
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![PDF_FILE])
+                        .default_type(PDF_FILE)
+                        .title("Save a copy with bookmarks, tags, highlights and notes")
+                        .button_text("Export");
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options));
+                } else if let Some(file_info) = cmd.get(druid::commands::SAVE_FILE_AS) {
+                    // the same save dialog result serves both kinds of export
+                    let path = file_info.path();
+                    let tags = tag_table(&data.document_info, &data.preferences.default_tag_table);
+                    if path.extension().map_or(false, |ext| ext == "pdf") {
+                        if let Err(e) = write_annotated_pdf(
+                            &data.document.filepath,
+                            &data.document_info,
+                            &tags,
+                            path,
+                            data.crop_exported_pdf,
+                        ) {
+                            println!("Error writing annotated PDF: {}", e);
+                        }
+                    } else {
+                        export_to_file(&data.document_info, &tags, path);
+                    }
+                } else if let Some(page) = cmd.get(GO_TO_BOOKMARK) {
+                    data.set_visible_scroll_position(ctx.window_id(), *page, None);
+                    data.history.push_back(data.overview_selected_page);
+                    data.select_page(*page);
+                } else if let Some(name) = cmd.get(REMOVE_BOOKMARK) {
+                    data.document.doc_info_changed = true;
+                    data.document_info.bookmarks.remove(name);
+                    data.document
+                        .generate_reverse_bookmarks(&data.document_info);
                 } else if let Some(new_mode) = cmd.get(SET_WINDOW_MODE) {
                     println!("HII");
                     if *new_mode == WindowMode::Normal {
                         ctx.request_focus();
                         data.window_mode = *new_mode;
+                    } else if *new_mode == WindowMode::Bookmarks {
+                        // opened from the context menu, the panel has no text box to take focus
+                        data.window_mode = *new_mode;
                     }
                 } else {
                     child.event(ctx, event, data, env);
@@ -1194,6 +1571,10 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                                 data.window_mode = WindowMode::Goto;
                             },
                             "f" => data.window_mode = WindowMode::Find,
+                            // with shift
+                            "F" => ctx.submit_command(SHOW_LIBRARY_SEARCH),
+                            "t" => data.window_mode = WindowMode::TagQuery,
+                            "m" => data.window_mode = WindowMode::Bookmarks,
                             "j" => ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Normal)),
 
 
@@ -1202,10 +1583,13 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                     }
                 // } else if e.key == Key::Escape {
                 //     data.window_mode = WindowMode::Normal;
+                } else if e.key == Key::F3 && !data.search_hits.is_empty() {
+                    // with the find bar open but the pages focused
+                    data.step_search_hit(ctx.window_id(), !e.mods.shift());
                 } else if e.key == Key::Character("/".to_string()) || e.key == Key::F3 {
                     data.window_mode = WindowMode::Find;
                 } else if e.key == Key::Escape {
-                    data.search_results.borrow_mut().clear();
+                    data.stop_search();
                     data.window_mode = WindowMode::Normal;
                     ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Normal));
                     ctx.set_handled();
@@ -1224,6 +1608,12 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                     } else {
                         data.scrollbar_position = data.scrollbar_position.next();
                     }
+                } else if e.key == Key::PageDown || e.key == Key::PageUp {
+                    if let Some(page) =
+                        data.next_tagged_page(data.page_number, e.key == Key::PageDown)
+                    {
+                        data.show_page(ctx.window_id(), page);
+                    }
                 } else if e.key == Key::Enter {
                     if data.page_number != data.overview_selected_page {
                         data.history.push_back(data.overview_selected_page);
@@ -1253,7 +1643,28 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                     }
                 } else if !e.mods.ctrl() && !e.mods.alt() {
                     let s = e.key.to_string();
-                    if s == " " {
+                    if data.global_mark_pending && s.chars().count() == 1 {
+                        // the letter after ' names a global mark, see global_marks.rs
+                        data.global_mark_pending = false;
+                        if let Some(ch) = s.chars().next().filter(|ch| ch.is_alphabetic()) {
+                            let mark = (
+                                ch.to_uppercase().to_string(),
+                                data.document_info.fingerprint.clone(),
+                                data.page_number_or_link_target(),
+                            );
+                            if ch.is_uppercase() {
+                                ctx.submit_command(SET_GLOBAL_MARK.with(mark));
+                            } else {
+                                ctx.submit_command(GLOBAL_MARK_KEY.with(mark));
+                            }
+                        }
+                    } else if s == "'" {
+                        data.global_mark_pending = true;
+                    } else if s == " " {
+                        ctx.submit_command(CLEAR_GLOBAL_MARKS.with((
+                            data.document_info.fingerprint.clone(),
+                            data.page_number_or_link_target(),
+                        )));
                         if let Some(s) = data
                             .document
                             .reverse_bookmarks
@@ -1290,9 +1701,16 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                                 } else {
                                     let page = data.page_number_or_link_target();
 
-                                    // forget any bookmarks which were assigned to this page
-                                    if let Some(s) = data.document.reverse_bookmarks.get(&page) {
-                                        data.document_info.bookmarks.remove(s);
+                                    // forget any other letter assigned to this page, named bookmarks stay
+                                    let letters: Vec<String> = data
+                                        .document_info
+                                        .bookmarks
+                                        .iter()
+                                        .filter(|(name, p)| **p == page && is_letter_shortcut(name))
+                                        .map(|(name, _)| name.clone())
+                                        .collect();
+                                    for name in letters {
+                                        data.document_info.bookmarks.remove(&name);
                                     }
 
                                     data.document_info.add_bookmark(&ch.to_string(), page);
@@ -1348,6 +1766,11 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
                 child.event(ctx, event, data, env)
             }
         }
+
+        // time is credited every tick, and whenever the page changes so it goes to the page that was actually read
+        if reading_tick || self.reading.page() != Some(data.page_number) {
+            self.credit_reading(data, false);
+        }
     }
 
     fn lifecycle(
@@ -1359,12 +1782,14 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
         env: &Env,
     ) {
         match event {
+            LifeCycle::WidgetAdded => {
+                self.reading.timer = ctx.request_timer(READING_TICK);
+                child.lifecycle(ctx, event, data, env)
+            }
             LifeCycle::HotChanged(_now) => {
-                if data.document.doc_info_changed && !ctx.is_hot() {
-                    // save on focus lost
-                    ctx.submit_command(
-                        SAVE_DOCUMENT_INFO.with(data.document_info.fingerprint.clone()),
-                    );
+                if !ctx.is_hot() {
+                    // stops the clock, then saves what's changed
+                    ctx.submit_command(PAUSE_READING);
                 }
                 child.lifecycle(ctx, event, data, env)
             }
@@ -1375,7 +1800,12 @@ impl<W: Widget<PdfViewState>> Controller<PdfViewState, W> for PdfWindowControlle
 
 use std::convert::TryInto;
 
-pub fn make_context_menu(data: &mut PdfViewState, page_number: PageNum) -> Menu<AppState> {
+// `mouse_pos` is in page coordinates
+pub fn make_context_menu(
+    data: &mut PdfViewState,
+    page_number: PageNum,
+    mouse_pos: Point,
+) -> Menu<AppState> {
     let scroll_vert = LocalizedString::new("Vertical page scroll direction");
     let scroll_horiz = LocalizedString::new("Horizontal page scroll direction");
 
@@ -1436,6 +1866,14 @@ pub fn make_context_menu(data: &mut PdfViewState, page_number: PageNum) -> Menu<
                 .on_activate(|ctx, _data, _env| ctx.submit_command(REPOSITION_OVERVIEW))
                 .hotkey(SysMods::None, Key::Tab),
         )
+        .entry(
+            MenuItem::new(if data.show_reading_heat {
+                LocalizedString::new("Hide reading time in the overview")
+            } else {
+                LocalizedString::new("Show reading time in the overview")
+            })
+            .on_activate(|ctx, _data, _env| ctx.submit_command(TOGGLE_READING_HEAT)),
+        )
         .entry(
             MenuItem::new(if data.scroll_direction == Axis::Vertical {
                 scroll_horiz
@@ -1470,7 +1908,82 @@ pub fn make_context_menu(data: &mut PdfViewState, page_number: PageNum) -> Menu<
         }
     }
 
+    let highlight_under_mouse = data
+        .document_info
+        .highlights
+        .get(&page_number)
+        .and_then(|highlights| {
+            highlights
+                .iter()
+                .position(|h| h.contains(mouse_pos))
+                .map(|idx| (idx, highlights[idx].bounds()))
+        });
+
+    if let Some((idx, _)) = highlight_under_mouse {
+        menu = menu.entry(
+            MenuItem::new(LocalizedString::new("Remove this highlight")).on_activate(
+                move |ctx, _data, _env| ctx.submit_command(REMOVE_HIGHLIGHT.with((page_number, idx))),
+            ),
+        );
+    }
+
+    let note = |idx: Option<usize>, region: Option<Rect>| NoteEdit {
+        fingerprint: data.document_info.fingerprint.clone(),
+        page: page_number,
+        idx,
+        region,
+    };
+
+    if let Some(notes) = data.document_info.notes.get(&page_number) {
+        for (idx, n) in notes.iter().enumerate() {
+            if n.applies_at(mouse_pos) {
+                let edit = note(Some(idx), n.region);
+                menu = menu.entry(
+                    MenuItem::new(format!("Edit note: {}", n.preview()))
+                        .on_activate(move |ctx, _data, _env| {
+                            ctx.submit_command(EDIT_NOTE.with(edit.clone()))
+                        }),
+                );
+            }
+        }
+    }
+
+    let page_note = note(None, None);
+    menu = menu.entry(
+        MenuItem::new(LocalizedString::new("Add a note to this page..."))
+            .on_activate(move |ctx, _data, _env| {
+                ctx.submit_command(EDIT_NOTE.with(page_note.clone()))
+            }),
+    );
+    if let Some((_, bounds)) = highlight_under_mouse {
+        let passage_note = note(None, Some(bounds));
+        menu = menu.entry(
+            MenuItem::new(LocalizedString::new("Add a note about this highlighted passage..."))
+                .on_activate(move |ctx, _data, _env| {
+                    ctx.submit_command(EDIT_NOTE.with(passage_note.clone()))
+                }),
+        );
+    }
+
+    let bookmark_at = (data.document_info.fingerprint.clone(), page_number);
+    menu = menu
+        .entry(
+            MenuItem::new(LocalizedString::new("Add a named bookmark to this page..."))
+                .on_activate(move |ctx, _data, _env| {
+                    ctx.submit_command(ADD_NAMED_BOOKMARK.with(bookmark_at.clone()))
+                }),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Bookmarks"))
+                .on_activate(|ctx, _data, _env| {
+                    ctx.submit_command(SET_WINDOW_MODE.with(WindowMode::Bookmarks))
+                })
+                .hotkey(SysMods::Cmd, "m"),
+        );
+
     let doc_idx = data.docu_idx;
+    let tags_fingerprint = data.document_info.fingerprint.clone();
+    let prerequisites_fingerprint = data.document_info.fingerprint.clone();
     menu = menu
         .entry(
             MenuItem::new(LocalizedString::new("Refresh window"))
@@ -1484,6 +1997,56 @@ pub fn make_context_menu(data: &mut PdfViewState, page_number: PageNum) -> Menu<
                 })
                 .hotkey(SysMods::Cmd, "b"),
         )
+        .entry(
+            MenuItem::new(LocalizedString::new("Search every book..."))
+                .on_activate(|ctx, _data, _env| ctx.submit_command(SHOW_LIBRARY_SEARCH))
+                .hotkey(SysMods::CmdShift, "f"),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Review due pages"))
+                .on_activate(|ctx, _data, _env| ctx.submit_command(REVIEW_DUE_PAGES)),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Prerequisites of this book...")).on_activate(
+                move |ctx, _data, _env| {
+                    ctx.submit_command(EDIT_PREREQUISITES.with(prerequisites_fingerprint.clone()))
+                },
+            ),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Reading order of the library"))
+                .on_activate(|ctx, _data, _env| ctx.submit_command(SHOW_READING_ORDER)),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Tag names and colours...")).on_activate(
+                move |ctx, _data, _env| {
+                    ctx.submit_command(EDIT_TAG_TABLE.with(Some(tags_fingerprint.clone())))
+                },
+            ),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Export notes and tags...")).on_activate(
+                |ctx, _data, _env| {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![MARKDOWN_FILE, ORG_FILE])
+                        .default_type(MARKDOWN_FILE)
+                        .title("Export notes, highlights, tags and bookmarks")
+                        .button_text("Export");
+                    ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(options))
+                },
+            ),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Export annotated PDF...")).on_activate(
+                |ctx, _data, _env| ctx.submit_command(EXPORT_ANNOTATED_PDF.with(false)),
+            ),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new(
+                "Export annotated PDF, cropped to margins...",
+            ))
+            .on_activate(|ctx, _data, _env| ctx.submit_command(EXPORT_ANNOTATED_PDF.with(true))),
+        )
         .entry(
             MenuItem::new(LocalizedString::new("Preferences..."))
                 .on_activate(|ctx, _data, _env| ctx.submit_command(SHOW_PREFERENCES))
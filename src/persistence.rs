@@ -0,0 +1,118 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// how many earlier versions of each book's info to keep in "<local data directory>/backups/<fingerprint>/"
+pub const BACKUPS_KEPT: usize = 20;
+
+// saves happen every time the mouse leaves a window, only keep a new backup if the newest is older than this
+const BACKUP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// write to a temporary file next to `path` then rename it over the top, so a crash or a sync client reading mid-write never sees a half-written file
+// the temporary file ends in ".tmp", which the filesystem watcher ignores
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn millis_since_epoch(time: SystemTime) -> u128 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+fn backups_dir_for(backups_dir: &Path, fingerprint: &str) -> PathBuf {
+    let mut dir = backups_dir.to_path_buf();
+    dir.push(fingerprint);
+    dir
+}
+
+// newest first, paired with when they were made
+pub fn list_backups(backups_dir: &Path, fingerprint: &str) -> Vec<(SystemTime, PathBuf)> {
+    let mut backups: Vec<(SystemTime, PathBuf)> =
+        match fs::read_dir(backups_dir_for(backups_dir, fingerprint)) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter_map(|path| {
+                    let millis = path.file_stem()?.to_str()?.parse::<u64>().ok()?;
+                    Some((
+                        SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+                        path,
+                    ))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+    backups
+}
+
+// copy the current version of a book's info file into its rolling history before it gets replaced
+pub fn back_up(backups_dir: &Path, fingerprint: &str, existing: &Path) {
+    let existing_contents = match fs::read(existing) {
+        Ok(contents) => contents,
+        Err(_) => return,
+    };
+
+    let backups = list_backups(backups_dir, fingerprint);
+    if let Some((newest_time, newest_path)) = backups.first() {
+        if newest_time.elapsed().unwrap_or(BACKUP_INTERVAL) < BACKUP_INTERVAL {
+            return;
+        }
+        if fs::read(newest_path).ok().as_ref() == Some(&existing_contents) {
+            return;
+        }
+    }
+
+    let dir = backups_dir_for(backups_dir, fingerprint);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        println!("Unable to create backups directory: {}", e);
+        return;
+    }
+
+    let mut path = dir;
+    path.push(millis_since_epoch(SystemTime::now()).to_string());
+    path.set_extension("json");
+
+    if let Err(e) = fs::write(&path, &existing_contents) {
+        println!("Error writing backup: {}", e);
+        return;
+    }
+
+    for (_, old) in list_backups(backups_dir, fingerprint)
+        .iter()
+        .skip(BACKUPS_KEPT)
+    {
+        let _ = fs::remove_file(old);
+    }
+}
+
+pub fn describe_age(time: SystemTime) -> String {
+    let secs = time.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+    if secs < 60 * 60 {
+        format!("{} minutes ago", secs / 60)
+    } else if secs < 60 * 60 * 48 {
+        format!("{} hours ago", secs / (60 * 60))
+    } else {
+        format!("{} days ago", secs / (60 * 60 * 24))
+    }
+}
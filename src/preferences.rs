@@ -36,16 +36,21 @@ use druid::{
 };
 
 use druid::widget::{
-    Align, Axis, Container, Controller, Flex, Label, LineBreaking, Padding, Painter, RadioGroup,
+    Align, Axis, Button, Container, Controller, Flex, Label, LineBreaking, Padding, Painter, RadioGroup,
     Scope, ScopeTransfer, Slider, Split, TextBox, ViewSwitcher,
 };
 
 use druid::commands::{COPY, CUT, PASTE, SHOW_PREFERENCES, UNDO};
 
+use druid::im::Vector;
+
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::path::{Path, PathBuf};
 
+use crate::library::SCAN_LIBRARY;
+use crate::review::DEFAULT_REVIEW_TAG;
+use crate::tag_table::{default_tag_table, TagDef, EDIT_TAG_TABLE, FIRST_TAG, TAG_COUNT};
 use crate::AppState;
 
 #[derive(Clone, Data, Debug, PartialEq, Serialize, Deserialize)]
@@ -77,6 +82,18 @@ pub struct Preferences {
     pub syncable_data_directory: String,
     pub brightness_inversion_amount: f64,
     pub scrollbar_layout: ScrollbarLayout,
+    #[serde(default = "default_tag_table")]
+    pub default_tag_table: Vector<TagDef>,
+    // pages with this tag are scheduled for spaced repetition review
+    #[serde(default = "default_review_tag")]
+    pub review_tag: usize,
+    // folders searched for PDFs to add to the book list, one per line
+    #[serde(default)]
+    pub library_folders: String,
+}
+
+fn default_review_tag() -> usize {
+    DEFAULT_REVIEW_TAG
 }
 
 impl Preferences {
@@ -94,6 +111,9 @@ impl Preferences {
             syncable_data_directory,
             brightness_inversion_amount: 0.97,
             scrollbar_layout: ScrollbarLayout::Grid,
+            default_tag_table: default_tag_table(),
+            review_tag: DEFAULT_REVIEW_TAG,
+            library_folders: String::new(),
         }
     }
 }
@@ -188,6 +208,47 @@ pub fn make_preferences_window() -> impl Widget<AppState> {
                         )
                     // , 1.)
             , 1.)
+        .with_child(
+            Align::new(UnitPoint::CENTER,
+                Button::new("Default tag names and colours...")
+                    .on_click(|ctx, _data: &mut AppState, _env| ctx.submit_command(EDIT_TAG_TABLE.with(None)))
+                    .padding(5.0)
+                )
+            )
+        .with_flex_child(
+            Flex::row()
+                .with_flex_child(
+                    Align::new(UnitPoint::RIGHT,
+                        Label::new(LocalizedString::new("Library folders, one per line: \r \rPDFs in these folders and the folders inside them are added to the list of books, and new ones are noticed as they arrive."))
+                            .with_line_break_mode(LineBreaking::WordWrap)
+                            .padding(5.0)
+                    )
+                    , 1.)
+                .with_flex_child(
+                    Flex::column()
+                        .with_flex_child(
+                            TextBox::multiline()
+                                .with_placeholder("~/Books")
+                                .expand()
+                                .padding(3.0)
+                                .lens(Preferences::library_folders).lens(AppState::preferences),
+                            1.)
+                        .with_child(
+                            Button::new("Scan now")
+                                .on_click(|ctx, _data: &mut AppState, _env| ctx.submit_command(SCAN_LIBRARY))
+                                .padding(3.0)
+                            )
+                , 1.)
+            , 2.)
+        .with_child(
+            Flex::row()
+                .with_child(Label::new("Pages to review are the ones tagged: "))
+                .with_child(
+                    RadioGroup::row((FIRST_TAG..TAG_COUNT).map(|bit| (bit.to_string(), bit)))
+                        .lens(Preferences::review_tag).lens(AppState::preferences)
+                    )
+                .padding(5.0)
+            )
         .padding(2.).controller(TextCopyPasteController)
 }
 
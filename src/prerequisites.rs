@@ -0,0 +1,552 @@
+use druid::im::{HashMap, Vector};
+use druid::kurbo::BezPath;
+use druid::piet::{FontFamily, Text, TextLayout, TextLayoutBuilder};
+use druid::widget::prelude::*;
+use druid::widget::{
+    Button, Checkbox, Flex, Label, LineBreaking, List, Scope, ScopeTransfer, Scroll, TextBox,
+};
+use druid::{
+    commands as sys_cmds, Color, Lens, LocalizedString, Point, Rect, Selector, WidgetExt,
+    WindowDesc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+
+use crate::export::page_label;
+use crate::{AppState, DocumentInfo, Fingerprint, PageNum, OPEN_BOOK_WITH_FINGERPRINT};
+
+// opens the window for choosing which books the given one depends on
+pub const EDIT_PREREQUISITES: Selector<Fingerprint> = Selector::new("edit-prerequisites");
+pub const SAVE_PREREQUISITES: Selector<(Fingerprint, Vector<PrerequisiteRow>)> =
+    Selector::new("save-prerequisites");
+pub const SHOW_READING_ORDER: Selector = Selector::new("show-reading-order");
+
+// stored with the book that needs the other one, keyed by the other one's fingerprint
+// which books a book is needed by isn't stored, it's found by looking through the others
+#[derive(Clone, Debug, Data, Serialize, Deserialize, PartialEq)]
+pub struct Prerequisite {
+    // the part of this book that needs the other one, such as a chapter, or None if it's all of it
+    pub pages: Option<(PageNum, PageNum)>,
+}
+
+fn description(all_books: &HashMap<Fingerprint, DocumentInfo>, fingerprint: &str) -> String {
+    all_books
+        .get(fingerprint)
+        .map(|info| info.description.clone())
+        .unwrap_or_else(|| "a book not seen on this machine".to_string())
+}
+
+fn pages_label(info: &DocumentInfo, pages: &Option<(PageNum, PageNum)>) -> String {
+    match pages {
+        Some((first, last)) => format!(
+            " (for {} to {})",
+            page_label(info, *first),
+            page_label(info, *last)
+        ),
+        None => String::new(),
+    }
+}
+
+fn needed_by<'a>(
+    info: &DocumentInfo,
+    all_books: &'a HashMap<Fingerprint, DocumentInfo>,
+) -> Vec<&'a DocumentInfo> {
+    let mut books: Vec<&DocumentInfo> = all_books
+        .values()
+        .filter(|other| other.prerequisites.contains_key(&info.fingerprint))
+        .collect();
+    books.sort_by(|a, b| a.description.cmp(&b.description));
+    books
+}
+
+// for the book list, empty for books with no prerequisites either way
+pub fn dependencies_summary(
+    info: &DocumentInfo,
+    all_books: &HashMap<Fingerprint, DocumentInfo>,
+) -> String {
+    let mut needs: Vec<String> = info
+        .prerequisites
+        .iter()
+        .map(|(fingerprint, prerequisite)| {
+            format!(
+                "{}{}",
+                description(all_books, fingerprint),
+                pages_label(info, &prerequisite.pages)
+            )
+        })
+        .collect();
+    needs.sort();
+    let needers: Vec<String> = needed_by(info, all_books)
+        .iter()
+        .map(|other| other.description.clone())
+        .collect();
+
+    let mut lines = Vec::<String>::new();
+    if !needs.is_empty() {
+        lines.push(format!("Needs: {}", needs.join("; ")));
+    }
+    if !needers.is_empty() {
+        lines.push(format!("Needed by: {}", needers.join("; ")));
+    }
+    lines.join("\n")
+}
+
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct PrerequisiteRow {
+    fingerprint: Fingerprint,
+    description: String,
+    needed: bool,
+    // printed page numbers, both blank if the whole book needs it
+    first_page: String,
+    last_page: String,
+}
+
+fn printed_page(info: &DocumentInfo, page: PageNum) -> String {
+    (page as i32 + 1 - info.page_offset).to_string()
+}
+
+fn page_from_printed(info: &DocumentInfo, printed: &str) -> Option<PageNum> {
+    let printed = printed.trim().parse::<i32>().ok()?;
+    let page = (printed - 1 + info.page_offset).max(0) as PageNum;
+    Some(if info.page_count > 0 {
+        page.min(info.page_count - 1)
+    } else {
+        page
+    })
+}
+
+// every other book, with the ones already needed first
+fn rows_for(
+    info: &DocumentInfo,
+    all_books: &HashMap<Fingerprint, DocumentInfo>,
+) -> Vector<PrerequisiteRow> {
+    let mut rows: Vec<PrerequisiteRow> = all_books
+        .values()
+        .filter(|other| other.fingerprint != info.fingerprint)
+        .map(|other| {
+            let prerequisite = info.prerequisites.get(&other.fingerprint);
+            let (first_page, last_page) = match prerequisite.and_then(|p| p.pages) {
+                Some((first, last)) => (printed_page(info, first), printed_page(info, last)),
+                None => (String::new(), String::new()),
+            };
+            PrerequisiteRow {
+                fingerprint: other.fingerprint.clone(),
+                description: other.description.clone(),
+                needed: prerequisite.is_some(),
+                first_page,
+                last_page,
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        b.needed
+            .cmp(&a.needed)
+            .then_with(|| a.description.cmp(&b.description))
+    });
+    rows.into_iter().collect()
+}
+
+pub fn set_prerequisites(info: &mut DocumentInfo, rows: &Vector<PrerequisiteRow>) {
+    for row in rows {
+        if row.needed {
+            let pages = match (
+                page_from_printed(info, &row.first_page),
+                page_from_printed(info, &row.last_page),
+            ) {
+                (Some(first), Some(last)) => Some((first.min(last), first.max(last))),
+                (Some(page), None) | (None, Some(page)) => Some((page, page)),
+                (None, None) => None,
+            };
+            info.prerequisites
+                .insert(row.fingerprint.clone(), Prerequisite { pages });
+        } else {
+            info.prerequisites.remove(&row.fingerprint);
+        }
+    }
+}
+
+#[derive(Clone, Data, Lens)]
+struct PrerequisitesEditor {
+    fingerprint: Fingerprint,
+    rows: Vector<PrerequisiteRow>,
+}
+
+// like the tag table editor, nothing changes until "Save" is clicked
+struct PrerequisitesEditorTransfer;
+
+impl ScopeTransfer for PrerequisitesEditorTransfer {
+    type In = AppState;
+    type State = PrerequisitesEditor;
+
+    fn read_input(&self, _my_state: &mut Self::State, _external: &Self::In) {}
+
+    fn write_back_input(&self, _my_state: &Self::State, _external: &mut Self::In) {}
+}
+
+fn make_prerequisite_row() -> impl Widget<PrerequisiteRow> {
+    Flex::row()
+        .with_child(Checkbox::new("").lens(PrerequisiteRow::needed))
+        .with_flex_child(
+            Label::new(|row: &PrerequisiteRow, _env: &_| row.description.clone())
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .expand_width(),
+            1.,
+        )
+        .with_default_spacer()
+        .with_child(Label::new("for pages"))
+        .with_child(
+            TextBox::new()
+                .with_placeholder("all")
+                .lens(PrerequisiteRow::first_page)
+                .fix_width(50.),
+        )
+        .with_child(Label::new("to"))
+        .with_child(
+            TextBox::new()
+                .with_placeholder("all")
+                .lens(PrerequisiteRow::last_page)
+                .fix_width(50.),
+        )
+        .padding((10., 3.))
+}
+
+pub fn make_prerequisites_window(
+    data: &AppState,
+    fingerprint: Fingerprint,
+) -> WindowDesc<AppState> {
+    let (heading, rows) = match data.all_local_documents_info.get(&fingerprint) {
+        Some(info) => {
+            let needers: Vec<String> = needed_by(info, &data.all_local_documents_info)
+                .iter()
+                .map(|other| format!("\"{}\"", other.description))
+                .collect();
+            let mut heading = format!(
+                "Books to read before \"{}\". Give page numbers if only part of it, such as a chapter, needs the other book.",
+                info.description
+            );
+            if !needers.is_empty() {
+                heading.push_str(&format!("\n\nIt's needed by {}.", needers.join(", ")));
+            }
+            (heading, rows_for(info, &data.all_local_documents_info))
+        }
+        None => (String::new(), Vector::new()),
+    };
+
+    let ui = Scope::from_function(
+        move |_app_state: AppState| PrerequisitesEditor {
+            fingerprint: fingerprint.clone(),
+            rows: rows.clone(),
+        },
+        PrerequisitesEditorTransfer,
+        Flex::column()
+            .with_child(
+                Label::new(heading)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(10.0)
+                    .expand_width(),
+            )
+            .with_flex_child(
+                Scroll::new(List::new(make_prerequisite_row))
+                    .vertical()
+                    .lens(PrerequisitesEditor::rows),
+                1.,
+            )
+            .with_child(
+                Flex::row()
+                    .with_child(Button::new("Save").on_click(
+                        |ctx, data: &mut PrerequisitesEditor, _env| {
+                            ctx.submit_command(
+                                SAVE_PREREQUISITES
+                                    .with((data.fingerprint.clone(), data.rows.clone())),
+                            );
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        },
+                    ))
+                    .with_default_spacer()
+                    .with_child(Button::new("Cancel").on_click(
+                        |ctx, _data: &mut PrerequisitesEditor, _env| {
+                            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                        },
+                    ))
+                    .padding(10.0),
+            ),
+    );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Prerequisites"))
+        .window_size((600., 500.))
+}
+
+// whether a book's prerequisites, followed among `books`, lead back round to it
+fn needs_itself(
+    all_books: &HashMap<Fingerprint, DocumentInfo>,
+    books: &HashSet<&Fingerprint>,
+    start: &Fingerprint,
+) -> bool {
+    let mut stack: Vec<&Fingerprint> = vec![start];
+    let mut seen: HashSet<&Fingerprint> = HashSet::new();
+    while let Some(fingerprint) = stack.pop() {
+        for needed in all_books[fingerprint].prerequisites.keys() {
+            if needed == start {
+                return true;
+            }
+            if books.contains(needed) && seen.insert(needed) {
+                stack.push(needed);
+            }
+        }
+    }
+    false
+}
+
+// the books with prerequisites either way, in columns: each book's prerequisites are all in earlier columns
+// books whose prerequisites lead round in a circle can't be placed, and are returned separately, followed by the
+// books that can't be placed because they need one of those
+pub fn reading_order(
+    all_books: &HashMap<Fingerprint, DocumentInfo>,
+) -> (Vec<Vec<Fingerprint>>, Vec<Fingerprint>, Vec<Fingerprint>) {
+    let mut remaining: HashSet<&Fingerprint> = HashSet::new();
+    for info in all_books.values() {
+        for fingerprint in info.prerequisites.keys() {
+            if all_books.contains_key(fingerprint) {
+                remaining.insert(&info.fingerprint);
+                remaining.insert(fingerprint);
+            }
+        }
+    }
+
+    let by_description = |a: &Fingerprint, b: &Fingerprint| {
+        description(all_books, a).cmp(&description(all_books, b))
+    };
+
+    let mut columns = Vec::<Vec<Fingerprint>>::new();
+    loop {
+        let mut column: Vec<Fingerprint> = remaining
+            .iter()
+            .filter(|fingerprint| {
+                all_books[**fingerprint]
+                    .prerequisites
+                    .keys()
+                    .all(|needed| !remaining.contains(needed))
+            })
+            .map(|fingerprint| (*fingerprint).clone())
+            .collect();
+        if column.is_empty() {
+            break;
+        }
+        for fingerprint in &column {
+            remaining.remove(fingerprint);
+        }
+        column.sort_by(by_description);
+        columns.push(column);
+    }
+
+    let (mut circular, mut blocked): (Vec<Fingerprint>, Vec<Fingerprint>) = remaining
+        .iter()
+        .map(|fingerprint| (*fingerprint).clone())
+        .partition(|fingerprint| needs_itself(all_books, &remaining, fingerprint));
+    circular.sort_by(by_description);
+    blocked.sort_by(by_description);
+    (columns, circular, blocked)
+}
+
+fn suggested_order(all_books: &HashMap<Fingerprint, DocumentInfo>) -> String {
+    let (columns, circular, blocked) = reading_order(all_books);
+    if columns.is_empty() && circular.is_empty() {
+        return "No prerequisites have been given yet. Use the \"Prerequisites\" button in the book list to say which books need which."
+            .to_string();
+    }
+
+    let mut text = "Suggested reading order:\n".to_string();
+    for (i, fingerprint) in columns.iter().flatten().enumerate() {
+        text.push_str(&format!(
+            "\n{}. {}",
+            i + 1,
+            description(all_books, fingerprint)
+        ));
+    }
+    if !circular.is_empty() {
+        text.push_str("\n\nThese need each other in a circle, so can't be put in order:\n");
+        for fingerprint in &circular {
+            text.push_str(&format!("\n{}", description(all_books, fingerprint)));
+        }
+    }
+    if !blocked.is_empty() {
+        text.push_str(
+            "\n\nThese need books in that circle, so can't be put in order until it's broken:\n",
+        );
+        for fingerprint in &blocked {
+            text.push_str(&format!("\n{}", description(all_books, fingerprint)));
+        }
+    }
+    text
+}
+
+const COLUMN_WIDTH: f64 = 240.;
+const ROW_HEIGHT: f64 = 60.;
+const BOX_SIZE: (f64, f64) = (190., 44.);
+
+// the library's prerequisites as boxes joined by arrows, read left to right, clicking a box opens the book
+struct ReadingOrderGraph {
+    boxes: Vec<(Fingerprint, Rect)>,
+    circular: HashSet<Fingerprint>,
+    blocked: HashSet<Fingerprint>,
+}
+
+impl ReadingOrderGraph {
+    fn box_of(&self, fingerprint: &str) -> Option<Rect> {
+        self.boxes
+            .iter()
+            .find(|(fp, _)| fp == fingerprint)
+            .map(|(_, rect)| *rect)
+    }
+}
+
+impl Widget<AppState> for ReadingOrderGraph {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut AppState, _env: &Env) {
+        if let Event::MouseDown(e) = event {
+            if let Some((fingerprint, _)) = self.boxes.iter().find(|(_, rect)| rect.contains(e.pos))
+            {
+                ctx.submit_command(OPEN_BOOK_WITH_FINGERPRINT.with(fingerprint.clone()));
+                ctx.set_handled();
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &AppState,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, _env: &Env) {
+        if !old_data
+            .all_local_documents_info
+            .same(&data.all_local_documents_info)
+        {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &AppState,
+        _env: &Env,
+    ) -> Size {
+        let (mut columns, circular, blocked) = reading_order(&data.all_local_documents_info);
+        self.circular = circular.iter().cloned().collect();
+        self.blocked = blocked.iter().cloned().collect();
+        // circular books go in a column of their own at the end, followed by the books waiting on them
+        if !circular.is_empty() {
+            columns.push(circular);
+        }
+        if !blocked.is_empty() {
+            columns.push(blocked);
+        }
+
+        self.boxes.clear();
+        let mut tallest = 0;
+        for (x, column) in columns.iter().enumerate() {
+            for (y, fingerprint) in column.iter().enumerate() {
+                let origin = Point::new(x as f64 * COLUMN_WIDTH + 20., y as f64 * ROW_HEIGHT + 20.);
+                self.boxes.push((
+                    fingerprint.clone(),
+                    Rect::from_origin_size(origin, BOX_SIZE),
+                ));
+            }
+            tallest = tallest.max(column.len());
+        }
+
+        bc.constrain(Size::new(
+            columns.len() as f64 * COLUMN_WIDTH + 20.,
+            tallest as f64 * ROW_HEIGHT + 20.,
+        ))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
+        let size = ctx.size();
+        ctx.fill(size.to_rect(), &Color::BLACK);
+
+        // an arrow from each prerequisite to each book that needs it
+        for (fingerprint, rect) in &self.boxes {
+            if let Some(info) = data.all_local_documents_info.get(fingerprint) {
+                for needed in info.prerequisites.keys() {
+                    if let Some(from) = self.box_of(needed) {
+                        let start = Point::new(from.x1, from.center().y);
+                        let end = Point::new(rect.x0, rect.center().y);
+                        let bend = (end.x - start.x).abs().max(40.) * 0.5;
+                        let mut path = BezPath::new();
+                        path.move_to(start);
+                        path.curve_to(
+                            Point::new(start.x + bend, start.y),
+                            Point::new(end.x - bend, end.y),
+                            end,
+                        );
+                        ctx.stroke(path, &Color::grey(0.6), 2.);
+                        ctx.fill(Rect::from_center_size(end, (6., 6.)), &Color::grey(0.8));
+                    }
+                }
+            }
+        }
+
+        for (fingerprint, rect) in &self.boxes {
+            ctx.fill(*rect, &Color::grey(0.2));
+            let outline = if self.circular.contains(fingerprint) {
+                Color::rgb8(220, 60, 60)
+            } else if self.blocked.contains(fingerprint) {
+                Color::rgb8(220, 160, 60)
+            } else {
+                Color::grey(0.6)
+            };
+            ctx.stroke(*rect, &outline, 1.5);
+
+            let layout = ctx
+                .text()
+                .new_text_layout(description(&data.all_local_documents_info, fingerprint))
+                .font(FontFamily::SANS_SERIF, 12.)
+                .text_color(Color::WHITE)
+                .max_width(rect.width() - 10.)
+                .build()
+                .unwrap();
+            let text_height = layout.size().height.min(rect.height() - 6.);
+            ctx.with_save(|ctx| {
+                ctx.clip(*rect);
+                ctx.draw_text(&layout, (rect.x0 + 5., rect.center().y - text_height * 0.5));
+            });
+        }
+    }
+}
+
+pub fn make_reading_order_window() -> WindowDesc<AppState> {
+    let ui = Flex::row()
+        .with_child(
+            Scroll::new(
+                Label::new(|data: &AppState, _env: &_| {
+                    suggested_order(&data.all_local_documents_info)
+                })
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .padding(10.0)
+                .fix_width(280.),
+            )
+            .vertical(),
+        )
+        .with_flex_child(
+            Scroll::new(ReadingOrderGraph {
+                boxes: Vec::new(),
+                circular: HashSet::new(),
+                blocked: HashSet::new(),
+            })
+            .expand(),
+            1.,
+        );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Reading order"))
+        .window_size((1000., 600.))
+}
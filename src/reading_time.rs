@@ -0,0 +1,177 @@
+use druid::{Selector, TimerToken, WindowId};
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{DocumentInfo, PageNum};
+
+// shows or hides how long each page has been read, as a glow behind its dot in the overview panel
+pub const TOGGLE_READING_HEAT: Selector = Selector::new("toggle-reading-heat");
+// sent to every window by the one the reader has just turned to, the others stop their clocks
+pub const READING_WINDOW: Selector<WindowId> = Selector::new("reading-window");
+// the mouse has left the window, the nearest druid comes to telling a window it has lost focus
+pub const PAUSE_READING: Selector = Selector::new("pause-reading");
+
+// how often the page on screen is credited with the time spent on it
+pub const READING_TICK: Duration = Duration::from_secs(10);
+// with no mouse or keyboard input for this long the reader has probably walked away, so the clock stops until they're back
+const IDLE_LIMIT: Duration = Duration::from_secs(5 * 60);
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// kept by each view window's controller
+// only one window's clock runs at a time, so duplicate windows and ones left in the background aren't credited too
+pub struct ReadingClock {
+    pub timer: TimerToken,
+    page: Option<PageNum>,
+    // time before this has already been credited to a page
+    counted_until: Instant,
+    last_input: Instant,
+    // another window is being read, or the mouse has left this one
+    paused: bool,
+}
+
+impl ReadingClock {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        ReadingClock {
+            timer: TimerToken::INVALID,
+            page: None,
+            counted_until: now,
+            last_input: now,
+            paused: false,
+        }
+    }
+
+    pub fn page(&self) -> Option<PageNum> {
+        self.page
+    }
+
+    // true if the clock was paused and has started again, so the other windows should be told to stop theirs
+    pub fn input(&mut self) -> bool {
+        let now = Instant::now();
+        let resumed = self.paused;
+        // back after a break, which wasn't spent reading
+        if resumed || now.duration_since(self.last_input) > IDLE_LIMIT {
+            self.counted_until = now;
+        }
+        self.paused = false;
+        self.last_input = now;
+        resumed
+    }
+
+    // credits the time read so far, like `take`, then stops counting until there's input again
+    pub fn pause(&mut self, page: PageNum) -> Option<(PageNum, u64)> {
+        let credited = self.take(page);
+        self.paused = true;
+        credited
+    }
+
+    // whole seconds spent on the previous page since the last call, `page` is the one now on screen
+    // fractions of a second carry over to whichever page is read next
+    pub fn take(&mut self, page: PageNum) -> Option<(PageNum, u64)> {
+        let now = Instant::now();
+        let previous = self.page.replace(page);
+        if previous.is_none() || self.paused {
+            self.counted_until = now;
+            return None;
+        }
+
+        let end = now.min(self.last_input + IDLE_LIMIT);
+        let seconds = if end > self.counted_until {
+            end.duration_since(self.counted_until).as_secs()
+        } else {
+            0
+        };
+        self.counted_until += Duration::from_secs(seconds);
+
+        match previous {
+            Some(previous) if seconds > 0 => Some((previous, seconds)),
+            _ => None,
+        }
+    }
+}
+
+pub fn days_since_epoch() -> i64 {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    (seconds / SECONDS_PER_DAY) as i64
+}
+
+// "YYYY-MM-DD", from Howard Hinnant's civil_from_days
+// days are counted in UTC, which is near enough for a study log and needs no time zone database
+pub fn date(days_since_epoch: i64) -> String {
+    let z = days_since_epoch + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn today() -> String {
+    date(days_since_epoch())
+}
+
+pub fn add_reading_time(info: &mut DocumentInfo, page: PageNum, seconds: u64) {
+    *info.reading_time.entry(page).or_insert(0) += seconds;
+    *info.daily_reading_time.entry(today()).or_insert(0) += seconds;
+}
+
+pub fn page_reading_time(info: &DocumentInfo, page: PageNum) -> u64 {
+    *info.reading_time.get(&page).unwrap_or(&0)
+}
+
+// 0 for unread pages up to 1 for the most read one, for the overview panel's heat overlay
+pub fn page_heat(info: &DocumentInfo, page: PageNum, most_read: u64) -> f64 {
+    if most_read == 0 {
+        0.
+    } else {
+        // square root, so a page glanced at still shows next to one pored over for an hour
+        (page_reading_time(info, page) as f64 / most_read as f64).sqrt()
+    }
+}
+
+pub fn most_read_page_time(info: &DocumentInfo) -> u64 {
+    info.reading_time.values().copied().max().unwrap_or(0)
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    }
+}
+
+// for the book list, empty if no time has been recorded yet
+pub fn reading_summary(info: &DocumentInfo) -> String {
+    let total: u64 = info.daily_reading_time.values().sum();
+    if total == 0 {
+        return String::new();
+    }
+
+    let today = days_since_epoch();
+    let this_week: u64 = (0..7)
+        .filter_map(|days_ago| info.daily_reading_time.get(&date(today - days_ago)))
+        .sum();
+    let days_read = info
+        .daily_reading_time
+        .values()
+        .filter(|seconds| **seconds > 0)
+        .count();
+
+    format!(
+        "read for {} over {} days, {} in the last week",
+        format_duration(total),
+        days_read,
+        format_duration(this_week)
+    )
+}
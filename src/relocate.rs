@@ -0,0 +1,172 @@
+use druid::widget::prelude::*;
+use druid::widget::{Button, Flex, Label, LineBreaking, List, Scroll};
+use druid::{
+    ExtEventSink, FileDialogOptions, FileInfo, FileSpec, Lens, LocalizedString, Selector, Target,
+    UnitPoint, WidgetExt, WindowDesc,
+};
+
+use mupdf::pdf::PdfDocument;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fingerprint::{identify, FingerprintAliases};
+use crate::library::find_pdfs;
+use crate::{canonical_path_string, AppState, Fingerprint, PageNum};
+
+// from the search thread, where the book turned up, if anywhere
+pub const BOOK_RELOCATED: Selector<(Fingerprint, Option<String>)> = Selector::new("book-relocated");
+// from the missing books window, asks for a file dialog to point out where the book is now
+pub const LOCATE_BOOK: Selector<Fingerprint> = Selector::new("locate-book");
+// from that file dialog
+pub const LOCATED_BOOK_FILE: Selector<FileInfo> = Selector::new("located-book-file");
+
+// a book that wasn't at any of the places it's been seen, nor anywhere it was looked for
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct MissingBook {
+    pub fingerprint: Fingerprint,
+    pub summary: String,
+}
+
+// what's known about the file being looked for, so most PDFs can be ruled out without working out their fingerprint
+pub struct WantedBook {
+    pub fingerprint: Fingerprint,
+    // from when it was last opened on this machine, None for books only seen on other machines
+    pub file_size: Option<u64>,
+    // 0 if not known
+    pub page_count: PageNum,
+    // files whose own fingerprint isn't the book's, but which are filed under it
+    pub aliases: FingerprintAliases,
+}
+
+impl WantedBook {
+    fn could_be(&self, path: &Path) -> bool {
+        match (self.file_size, fs::metadata(path)) {
+            (Some(size), Ok(metadata)) => metadata.len() == size,
+            (Some(_), Err(_)) => false,
+            (None, _) => true,
+        }
+    }
+
+    fn is(&self, path_string: &str) -> bool {
+        let pdf_doc = match PdfDocument::open(path_string) {
+            Ok(pdf_doc) => pdf_doc,
+            Err(_) => return false,
+        };
+        if self.page_count != 0 && pdf_doc.page_count().unwrap_or(0) as PageNum != self.page_count {
+            return false;
+        }
+        let identity = identify(&pdf_doc, path_string);
+        identity.fingerprint == self.fingerprint
+            || identity.legacy == self.fingerprint
+            || self.aliases.get(&identity.fingerprint) == Some(&self.fingerprint)
+    }
+}
+
+// the library folders from the preferences first, then the places downloads and documents usually end up
+pub fn places_to_look(library_folders: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut places = library_folders;
+    if let Some(user_dirs) = directories::UserDirs::new() {
+        places.extend(
+            vec![
+                user_dirs.download_dir(),
+                user_dirs.document_dir(),
+                user_dirs.desktop_dir(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.to_path_buf()),
+        );
+    }
+    places
+}
+
+// walking folders and opening PDFs takes a while, so it's done away from the UI thread
+// `known_paths` are where the book isn't any more, or other books already, so they aren't opened again
+pub fn start_search(
+    sink: ExtEventSink,
+    wanted: WantedBook,
+    places: Vec<PathBuf>,
+    known_paths: HashSet<String>,
+) {
+    std::thread::spawn(move || {
+        let mut seen = HashSet::<String>::new();
+        let mut found = None;
+        'places: for place in &places {
+            let mut pdfs = Vec::<PathBuf>::new();
+            find_pdfs(place, &mut pdfs);
+            for path in pdfs {
+                let path_string = match canonical_path_string(&path) {
+                    Some(path_string) => path_string,
+                    None => continue,
+                };
+                // folders can be inside each other, or listed twice
+                if known_paths.contains(&path_string) || !seen.insert(path_string.clone()) {
+                    continue;
+                }
+                if wanted.could_be(&path) && wanted.is(&path_string) {
+                    found = Some(path_string);
+                    break 'places;
+                }
+            }
+        }
+
+        if let Err(e) = sink.submit_command(
+            BOOK_RELOCATED,
+            Box::new((wanted.fingerprint, found)),
+            Target::Auto,
+        ) {
+            println!("error sending where a moved book is: {}", e);
+        }
+    });
+}
+
+pub fn locate_dialog_options(description: &str) -> FileDialogOptions {
+    let pdf = FileSpec::new("PDF file", &["pdf"]);
+    FileDialogOptions::new()
+        .allowed_types(vec![pdf])
+        .default_type(pdf)
+        .title(format!("Where is \"{}\" now?", description))
+        .button_text("Locate")
+        .accept_command(LOCATED_BOOK_FILE)
+}
+
+fn make_missing_book_row() -> impl Widget<MissingBook> {
+    Flex::row()
+        .with_flex_child(
+            Label::new(|missing: &MissingBook, _env: &_| missing.summary.clone())
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .align_vertical(UnitPoint::LEFT)
+                .padding(10.0)
+                .expand_width(),
+            1.,
+        )
+        .with_child(
+            Button::new("Locate...").on_click(|ctx, missing: &mut MissingBook, _env| {
+                ctx.submit_command(LOCATE_BOOK.with(missing.fingerprint.clone()))
+            }),
+        )
+        .with_default_spacer()
+}
+
+pub fn make_missing_books_window() -> WindowDesc<AppState> {
+    let ui = Flex::column()
+        .with_child(
+            Label::new(
+                "These books weren't where they were last seen, nor in the library folders, Downloads, Documents or Desktop. Point out where they are now and they'll open from there, and from the book list after this.",
+            )
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding(10.0),
+        )
+        .with_flex_child(
+            Scroll::new(List::new(make_missing_book_row))
+                .vertical()
+                .lens(AppState::missing_books),
+            1.,
+        );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Missing books"))
+        .window_size((700., 300.))
+}
@@ -0,0 +1,218 @@
+use druid::im::{HashMap, Vector};
+use druid::keyboard_types::Key;
+use druid::widget::prelude::*;
+use druid::widget::{Button, Controller, Flex, Label, LineBreaking};
+use druid::{commands as sys_cmds, Lens, LocalizedString, Selector, WidgetExt, WindowDesc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::export::page_label;
+use crate::reading_time::{date, days_since_epoch, today};
+use crate::{AppState, DocumentInfo, Fingerprint, PageNum};
+
+// gathers every book's due pages and opens the review window on the first of them
+pub const REVIEW_DUE_PAGES: Selector = Selector::new("review-due-pages");
+// how well the page at the front of the queue was remembered, from 0 (not at all) to 5 (perfectly)
+pub const GRADE_REVIEW: Selector<u8> = Selector::new("grade-review");
+// moves on without rescheduling, the page stays due
+pub const SKIP_REVIEW: Selector = Selector::new("skip-review");
+
+pub const DEFAULT_REVIEW_TAG: usize = 1;
+
+const GRADES: [(u8, &str); 6] = [
+    (0, "0 forgotten"),
+    (1, "1 wrong"),
+    (2, "2 nearly"),
+    (3, "3 hard"),
+    (4, "4 good"),
+    (5, "5 easy"),
+];
+
+// a tagged page's place in the SM-2 schedule, pages without one are new and due straight away
+#[derive(Clone, Debug, Data, Serialize, Deserialize, PartialEq)]
+pub struct ReviewState {
+    pub repetitions: u32,
+    pub interval_days: u32,
+    pub ease: f64,
+    // "YYYY-MM-DD", which compare the same as strings as they do as dates
+    pub due: String,
+    pub last_reviewed: String,
+}
+
+impl ReviewState {
+    // SM-2: a failed recall starts the page over from a day, each good one multiplies the interval by the page's ease
+    pub fn graded(previous: Option<&ReviewState>, grade: u8) -> Self {
+        let grade = grade.min(5);
+        let (repetitions, interval_days, ease) = match previous {
+            Some(state) => (state.repetitions, state.interval_days, state.ease),
+            None => (0, 0, 2.5),
+        };
+
+        let (repetitions, interval_days) = if grade < 3 {
+            (0, 1)
+        } else {
+            let interval_days = match repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (interval_days as f64 * ease).round() as u32,
+            };
+            (repetitions + 1, interval_days)
+        };
+
+        let miss = (5 - grade) as f64;
+        let ease = f64::max(1.3, ease + 0.1 - miss * (0.08 + miss * 0.02));
+
+        ReviewState {
+            repetitions,
+            interval_days,
+            ease,
+            due: date(days_since_epoch() + interval_days as i64),
+            last_reviewed: today(),
+        }
+    }
+}
+
+pub fn grade_page(info: &mut DocumentInfo, page: PageNum, grade: u8) {
+    let state = ReviewState::graded(info.reviews.get(&page), grade);
+    info.reviews.insert(page, state);
+}
+
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct ReviewItem {
+    pub fingerprint: Fingerprint,
+    pub page: PageNum,
+    description: String,
+    page_label: String,
+}
+
+// pages carrying `review_tag` whose due date has come, most overdue first, new pages before those
+pub fn due_pages(
+    all_info: &HashMap<Fingerprint, DocumentInfo>,
+    review_tag: usize,
+) -> Vector<ReviewItem> {
+    let today = today();
+    let mut due = Vec::<(String, ReviewItem)>::new();
+
+    for info in all_info.values() {
+        for (page, bits) in info.tags.iter() {
+            if bits & (1 << review_tag) == 0 {
+                continue;
+            }
+            let due_date = info
+                .reviews
+                .get(page)
+                .map(|state| state.due.clone())
+                .unwrap_or_default();
+            if due_date <= today {
+                due.push((
+                    due_date,
+                    ReviewItem {
+                        fingerprint: info.fingerprint.clone(),
+                        page: *page,
+                        description: info.description.clone(),
+                        page_label: page_label(info, *page),
+                    },
+                ));
+            }
+        }
+    }
+
+    due.sort_by(|(a_due, a), (b_due, b)| {
+        a_due
+            .cmp(b_due)
+            .then_with(|| a.description.cmp(&b.description))
+            .then_with(|| a.page.cmp(&b.page))
+    });
+    due.into_iter().map(|(_, item)| item).collect()
+}
+
+fn review_heading(queue: &Vector<ReviewItem>) -> String {
+    match queue.front() {
+        Some(item) => format!(
+            "\"{}\", {}\n\n{} due. How well did you remember this page? Keys 0 to 5 grade it, S skips it.",
+            item.description,
+            item.page_label,
+            if queue.len() == 1 {
+                "1 page".to_string()
+            } else {
+                format!("{} pages", queue.len())
+            }
+        ),
+        None => "Nothing left to review today.".to_string(),
+    }
+}
+
+// grades with the number keys, so reviewing doesn't need the mouse
+struct ReviewKeysController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for ReviewKeysController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        match event {
+            Event::WindowConnected => {
+                ctx.request_focus();
+                child.event(ctx, event, data, env)
+            }
+            Event::KeyDown(e) => match &e.key {
+                Key::Character(s) if s.to_lowercase() == "s" => {
+                    ctx.submit_command(SKIP_REVIEW);
+                }
+                Key::Character(s) => match s.parse::<u8>() {
+                    Ok(grade) if grade <= 5 => ctx.submit_command(GRADE_REVIEW.with(grade)),
+                    _ => child.event(ctx, event, data, env),
+                },
+                Key::Escape => ctx.submit_command(sys_cmds::CLOSE_WINDOW),
+                _ => child.event(ctx, event, data, env),
+            },
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+pub fn make_review_window() -> WindowDesc<AppState> {
+    let mut grades = Flex::row();
+    for (grade, label) in GRADES.iter() {
+        let grade = *grade;
+        grades.add_child(
+            Button::new(*label).on_click(move |ctx, _data: &mut AppState, _env| {
+                ctx.submit_command(GRADE_REVIEW.with(grade))
+            }),
+        );
+    }
+
+    let ui = Flex::column()
+        .with_flex_child(
+            Label::new(|data: &AppState, _env: &_| review_heading(&data.review_queue))
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .padding(10.0)
+                .expand(),
+            1.,
+        )
+        .with_child(grades.padding(10.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new("Skip").on_click(|ctx, _data: &mut AppState, _env| {
+                        ctx.submit_command(SKIP_REVIEW)
+                    }),
+                )
+                .with_default_spacer()
+                .with_child(
+                    Button::new("Close").on_click(|ctx, _data: &mut AppState, _env| {
+                        ctx.submit_command(sys_cmds::CLOSE_WINDOW)
+                    }),
+                )
+                .padding(10.0),
+        )
+        .controller(ReviewKeysController);
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Review due pages"))
+        .window_size((520., 240.))
+}
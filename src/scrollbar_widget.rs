@@ -19,6 +19,9 @@ use crate::preferences::ScrollbarLayout;
 use crate::PageNum;
 
 use crate::find_goto_controllers::START_SEARCH;
+use crate::notes::{note_marker, NOTE_COLOR};
+use crate::reading_time::{format_duration, most_read_page_time, page_heat, page_reading_time};
+use crate::tag_table::{tag_names, tag_table};
 
 trait Scrollbar {
     fn layout(&mut self, size: Size);
@@ -35,6 +38,8 @@ pub struct ScrollbarWidget {
     length: usize,
     scrollbar: Box<dyn Scrollbar>,
     last_size_change: Instant,
+    // page under the mouse, whose tag names are shown next to it
+    hovered_page: Option<usize>,
 }
 
 #[derive(Default, Debug)]
@@ -377,6 +382,7 @@ impl ScrollbarWidget {
                 Box::new(Fractal::with_length(length))
             },
             last_size_change: Instant::now(),
+            hovered_page: None,
         }
     }
 }
@@ -387,7 +393,7 @@ use crate::pdf_text_widget::SHOW_GIVEN_PAGE;
 use crate::pdf_view::{MouseState, PageOverviewPosition};
 use crate::AppState;
 
-const COLORS: [druid::Color; 10] = [
+pub const COLORS: [druid::Color; 10] = [
     Color::BLACK,
     Color::rgb8(140, 70, 20),
     Color::RED,
@@ -407,29 +413,6 @@ impl Widget<PdfViewState> for ScrollbarWidget {
         }
         data.scrollbar_size = ctx.size();
         match event {
-            Event::AnimFrame(num) => {
-                if let Some((first, last)) = data.search_progress {
-                    let mut new_first = first;
-                    let mut new_last = last;
-
-                    for i in 0..50 {
-                        if new_first > 0 {
-                            new_first -= 1;
-                            data.search_page(new_first);
-                        }
-                        if new_last < data.document_info.page_count - 1 {
-                            new_last += 1;
-                            data.search_page(new_last);
-                        }
-                    }
-
-                    if new_first != first || new_last != last {
-                        data.search_progress = Some((new_first, new_last));
-                        ctx.request_paint();
-                        ctx.request_anim_frame();
-                    }
-                }
-            }
             Event::MouseMove(e) => {
                 if data.ignore_next_mouse_move {
                     data.ignore_next_mouse_move = false;
@@ -450,6 +433,10 @@ impl Widget<PdfViewState> for ScrollbarWidget {
                 }
 
                 let page = self.scrollbar.nearest(e.pos);
+                if self.hovered_page != Some(page) {
+                    self.hovered_page = Some(page);
+                    ctx.request_paint();
+                }
                 data.set_visible_scroll_position(ctx.window_id(), page, None);
             }
             Event::MouseDown(e) => {
@@ -465,15 +452,8 @@ impl Widget<PdfViewState> for ScrollbarWidget {
 
             Event::Command(cmd) => match cmd {
                 _ if cmd.is(START_SEARCH) => {
-                    data.search_results.borrow_mut().clear();
-                    if data.find_goal == "" {
-                        data.search_progress = None;
-                        ctx.request_paint();
-                    } else {
-                        data.search_page(data.page_number);
-                        data.search_progress = Some((data.page_number, data.page_number));
-                    }
-                    ctx.request_anim_frame();
+                    data.start_search(ctx.get_external_handle(), ctx.window_id());
+                    ctx.request_paint();
                 }
                 _ if cmd.is(SET_SCROLLBAR_LAYOUT_FRACTAL) => {
                     self.scrollbar = Box::new(Fractal::with_length(self.length));
@@ -499,6 +479,8 @@ impl Widget<PdfViewState> for ScrollbarWidget {
         match event {
             LifeCycle::HotChanged(now) => {
                 if !now {
+                    self.hovered_page = None;
+                    ctx.request_paint();
                     ctx.submit_command(
                         SHOW_GIVEN_PAGE
                             .with(data.overview_selected_page)
@@ -527,6 +509,9 @@ impl Widget<PdfViewState> for ScrollbarWidget {
             || data.mouse_over_hyperlink != old_data.mouse_over_hyperlink
             || data.document_info != old_data.document_info
             || data.overview_selected_page != old_data.overview_selected_page
+            || data.tag_query_text != old_data.tag_query_text
+            || data.show_reading_heat != old_data.show_reading_heat
+            || data.search_progress != old_data.search_progress
         {
             ctx.request_paint()
         }
@@ -608,6 +593,15 @@ impl Widget<PdfViewState> for ScrollbarWidget {
 
         let space = self.scrollbar.gap_between_nodes();
 
+        let tags_table = tag_table(&data.document_info, &data.preferences.default_tag_table);
+        // pages not matching the tag query bar's expression are dimmed
+        let query = data.tag_query();
+        let most_read = if data.show_reading_heat {
+            most_read_page_time(&data.document_info)
+        } else {
+            0
+        };
+
         let mut prev: Point = self.scrollbar.position(0);
         //let mut prev_colours = false;
 
@@ -630,10 +624,20 @@ impl Widget<PdfViewState> for ScrollbarWidget {
 
             //            let arc_path = BezP
 
-            let mut colours = Vec::<usize>::new();
+            // a warm square behind the dot, brighter the longer the page has been read
+            let heat = page_heat(&data.document_info, i, most_read);
+            if heat > 0. {
+                let square = Rect::from_center_size(pos, (si, si));
+                ctx.fill(square, &Color::rgb8(255, 120, 0).with_alpha(0.15 + 0.7 * heat));
+            }
+
+            let dimmed = query.as_ref().map_or(false, |q| !q.matches(tags));
+            let alpha = if dimmed { 0.25 } else { 1. };
+
+            let mut colours = Vec::<Color>::new();
             for bit in 0..=9 {
                 if (tags & (1 << bit)) != 0 {
-                    colours.push(bit);
+                    colours.push(tags_table[bit].color().with_alpha(alpha));
                 }
             }
 
@@ -653,6 +657,9 @@ impl Widget<PdfViewState> for ScrollbarWidget {
                 if in_cache {
                     color = Color::GRAY;
                 }
+                if dimmed {
+                    color = color.with_alpha(alpha);
+                }
 
                 if colours.is_empty() {
                     ctx.fill(Circle::new(pos, si * 0.2), &color);
@@ -672,7 +679,7 @@ impl Widget<PdfViewState> for ScrollbarWidget {
                         start_angle += sweep_angle;
                         // path.move_to(pos);
                         // path.push(arc);
-                        ctx.fill(arc, &COLORS[colours[a]]);
+                        ctx.fill(arc, &colours[a]);
                     }
                 }
 
@@ -705,10 +712,41 @@ impl Widget<PdfViewState> for ScrollbarWidget {
                 }
             }
 
+            // a bar under pages with highlights, in the first highlight's colour
+            if let Some(first) = data
+                .document_info
+                .highlights
+                .get(&i)
+                .and_then(|highlights| highlights.front())
+            {
+                let color = tags_table[first.color % tags_table.len()].color();
+                ctx.paint_with_z_index(2, move |ctx| {
+                    let bar = Rect::new(
+                        pos.x - si * 0.3,
+                        pos.y + si * 0.38,
+                        pos.x + si * 0.3,
+                        pos.y + si * 0.46,
+                    );
+                    ctx.fill(bar, &color);
+                });
+            }
+
+            if data.document_info.notes.contains_key(&i) {
+                ctx.paint_with_z_index(2, move |ctx| {
+                    let corner = Point::new(pos.x + si * 0.45, pos.y - si * 0.45);
+                    ctx.fill(note_marker(corner, si * 0.3), &NOTE_COLOR);
+                });
+            }
+
             if let Some(s) = data.document.check_for_bookmark(i) {
                 let color = Color::WHITE;
 
-                let label = s.clone();
+                // named bookmarks are too long to fit on a dot, their names show when it's moused over
+                let label = if s.chars().count() > 1 {
+                    format!("{}…", s.chars().next().unwrap_or(' '))
+                } else {
+                    s.clone()
+                };
                 ctx.paint_with_z_index(2, move |ctx| {
                     let text = ctx.text();
                     let layout = text
@@ -729,6 +767,48 @@ impl Widget<PdfViewState> for ScrollbarWidget {
             }
         }
 
+        // names of the hovered page's tags and bookmarks, so nobody has to remember what each colour means in this book
+        if let Some(page) = self.hovered_page {
+            let mut names = tag_names(data.document_info.tag_bits(page), &tags_table);
+            let mut bookmarks: Vec<String> = data
+                .document_info
+                .bookmarks
+                .iter()
+                .filter(|(_, p)| **p == page)
+                .map(|(name, _)| format!("bookmark: {}", name))
+                .collect();
+            bookmarks.sort();
+            names.extend(bookmarks);
+            let seconds = page_reading_time(&data.document_info, page);
+            if data.show_reading_heat && seconds > 0 {
+                names.push(format!("read for {}", format_duration(seconds)));
+            }
+            if !names.is_empty() && page < self.length {
+                let pos = self.scrollbar.position(page);
+                ctx.paint_with_z_index(3, move |ctx| {
+                    let layout = ctx
+                        .text()
+                        .new_text_layout(names.join("\n"))
+                        .font(FontFamily::SANS_SERIF, 12.)
+                        .text_color(Color::WHITE)
+                        .build()
+                        .unwrap();
+                    let sz = layout.size();
+                    // keep it inside the panel, to the left of the dot if there's no room on the right
+                    let x = if pos.x + space + sz.width + 8. < size.width {
+                        pos.x + space
+                    } else {
+                        (pos.x - space - sz.width - 8.).max(0.)
+                    };
+                    let y = pos.y.min(size.height - sz.height - 8.).max(0.);
+                    let background = Rect::from_origin_size((x, y), (sz.width + 8., sz.height + 8.));
+                    ctx.fill(background, &Color::rgba8(0, 0, 0, 220));
+                    ctx.stroke(background, &Color::grey(0.6), 1.);
+                    ctx.draw_text(&layout, (x + 4., y + 4.));
+                });
+            }
+        }
+
         let line_width = if data.scrollbar_layout == ScrollbarLayout::Grid {
             2.
         } else {
@@ -0,0 +1,165 @@
+use druid::im::Vector;
+use druid::widget::prelude::*;
+use druid::widget::{
+    Controller, CrossAxisAlignment, Flex, Label, LineBreaking, List, Painter, Scroll,
+};
+use druid::{Color, Lens, Rect, Selector, WidgetExt};
+
+use mupdf::outline::Outline;
+use mupdf::pdf::PdfDocument;
+
+use crate::export::page_label;
+use crate::pdf_view::PdfViewState;
+use crate::text_match::PageHit;
+use crate::{DocumentInfo, PageNum};
+
+// sent from the results panel to its own window, the hit's page and where it comes on that page
+pub const GO_TO_SEARCH_HIT: Selector<(PageNum, usize)> = Selector::new("go-to-search-hit");
+
+// every row is the same height, so the list can be scrolled to the current hit without asking it where its rows went
+const ROW_HEIGHT: f64 = 110.;
+
+const CURRENT_HIT_BACKGROUND: Color = Color::rgba8(240, 150, 10, 60);
+
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct SearchHit {
+    pub page: PageNum,
+    // hits are numbered in reading order within their page
+    pub nth: usize,
+    // in page-normalised coordinates, one per line the hit covers
+    pub rects: Vector<Rect>,
+    context: String,
+    chapter: String,
+    page_label: String,
+    // the one the view was last taken to
+    pub current: bool,
+}
+
+impl SearchHit {
+    pub fn new(
+        page: PageNum,
+        nth: usize,
+        hit: &PageHit,
+        chapters: &[(PageNum, String)],
+        info: &DocumentInfo,
+    ) -> Self {
+        SearchHit {
+            page,
+            nth,
+            rects: hit.rects.iter().cloned().collect(),
+            context: hit.context.clone(),
+            chapter: chapter_title(chapters, page),
+            page_label: page_label(info, page),
+            current: false,
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.rects.iter().skip(1).fold(
+            self.rects.front().cloned().unwrap_or_default(),
+            |r, next| r.union(*next),
+        )
+    }
+}
+
+fn flatten_outlines(outlines: &[Outline], chapters: &mut Vec<(PageNum, String)>) {
+    for entry in outlines {
+        if let Some(page) = entry.page {
+            chapters.push((page as PageNum, entry.title.trim().to_string()));
+        }
+        flatten_outlines(&entry.down, chapters);
+    }
+}
+
+// the table of contents as a list of where each entry starts, sections after the chapters they're in
+pub fn chapter_titles(pdf_doc: &PdfDocument) -> Vec<(PageNum, String)> {
+    let mut chapters = Vec::new();
+    if let Ok(outlines) = pdf_doc.outlines() {
+        flatten_outlines(&outlines, &mut chapters);
+    }
+    chapters
+}
+
+// the heading starting nearest before or on the page, outlines aren't always in page order
+// when several start on the same page the last listed wins, which is the innermost section in a well ordered outline
+fn chapter_title(chapters: &[(PageNum, String)], page: PageNum) -> String {
+    chapters
+        .iter()
+        .filter(|(start, _)| *start <= page)
+        .max_by_key(|(start, _)| *start)
+        .map(|(_, title)| title.clone())
+        .unwrap_or_default()
+}
+
+fn make_hit_row() -> impl Widget<SearchHit> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(|hit: &SearchHit, _env: &_| {
+                if hit.chapter.is_empty() {
+                    hit.page_label.clone()
+                } else {
+                    format!("{}, {}", hit.page_label, hit.chapter)
+                }
+            })
+            .with_text_color(Color::grey(0.7))
+            .with_line_break_mode(LineBreaking::Clip),
+        )
+        .with_child(
+            Label::new(|hit: &SearchHit, _env: &_| hit.context.clone())
+                .with_text_size(14.)
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .padding((8., 4.))
+        .expand_width()
+        .fix_height(ROW_HEIGHT)
+        .background(Painter::new(|ctx, hit: &SearchHit, _env| {
+            if hit.current {
+                let rect = ctx.size().to_rect();
+                ctx.fill(rect, &CURRENT_HIT_BACKGROUND);
+            }
+        }))
+        .on_click(|ctx, hit: &mut SearchHit, _env| {
+            ctx.submit_command(GO_TO_SEARCH_HIT.with((hit.page, hit.nth)))
+        })
+}
+
+// keeps the current hit in sight as the keyboard moves through them
+struct FollowCurrentHit;
+
+impl<W: Widget<Vector<SearchHit>>> Controller<Vector<SearchHit>, Scroll<Vector<SearchHit>, W>>
+    for FollowCurrentHit
+{
+    fn update(
+        &mut self,
+        child: &mut Scroll<Vector<SearchHit>, W>,
+        ctx: &mut UpdateCtx,
+        old_data: &Vector<SearchHit>,
+        data: &Vector<SearchHit>,
+        env: &Env,
+    ) {
+        child.update(ctx, old_data, data, env);
+
+        let current = |hits: &Vector<SearchHit>| {
+            hits.iter()
+                .find(|hit| hit.current)
+                .map(|hit| (hit.page, hit.nth))
+        };
+        if current(data) != current(old_data) {
+            if let Some(idx) = data.iter().position(|hit| hit.current) {
+                let y = idx as f64 * ROW_HEIGHT;
+                child.scroll_to(Rect::new(0., y, 1., y + ROW_HEIGHT));
+                ctx.request_paint();
+            }
+        }
+    }
+}
+
+// shown beside the pages in WindowMode::Find
+pub fn make_search_results_panel() -> impl Widget<PdfViewState> {
+    Scroll::new(List::new(make_hit_row))
+        .vertical()
+        .controller(FollowCurrentHit)
+        .lens(PdfViewState::search_hits)
+        .fix_width(320.)
+}
@@ -0,0 +1,128 @@
+use druid::{ExtEventSink, Rect, Selector, Target, WindowId};
+
+use mupdf::pdf::PdfDocument;
+use mupdf::Page;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::highlights::page_chars;
+use crate::text_match::{Matcher, PageHit};
+use crate::PageNum;
+
+// from the search thread to the window that started it, handled by the overview panel
+pub const SEARCH_BATCH: Selector<SearchBatch> = Selector::new("search-batch");
+
+// results are sent back this many pages at a time, so the overview fills in steadily without a command per page
+const PAGES_PER_BATCH: usize = 20;
+
+pub struct SearchBatch {
+    // the flag the search was started with, results from a search since cancelled are ignored
+    pub cancelled: Arc<AtomicBool>,
+    // every page from `first` to `last` has now been searched
+    pub first: PageNum,
+    pub last: PageNum,
+    pub hits: Vec<(PageNum, Vec<PageHit>)>,
+}
+
+pub fn cancel(flag: &Option<Arc<AtomicBool>>) {
+    if let Some(flag) = flag {
+        flag.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn page_hits(page: &Page, matcher: &Matcher) -> Vec<PageHit> {
+    let mut lines = Vec::<Vec<char>>::new();
+    let mut rects = Vec::<Vec<Rect>>::new();
+    for c in page_chars(page) {
+        while lines.len() <= c.line {
+            lines.push(Vec::new());
+            rects.push(Vec::new());
+        }
+        lines[c.line].push(c.ch);
+        rects[c.line].push(c.rect);
+    }
+
+    matcher
+        .find(&lines)
+        .into_iter()
+        .map(|hit| PageHit {
+            rects: hit
+                .spans
+                .into_iter()
+                .map(|(line, first, last)| rects[line][first].union(rects[line][last]))
+                .collect(),
+            context: hit.context,
+        })
+        .collect()
+}
+
+// works outwards from `from_page` so hits near where the reader is turn up first
+// `filepath` is opened again on the search thread, the window's open document can't be sent between threads
+pub fn start_search(
+    sink: ExtEventSink,
+    window_id: WindowId,
+    filepath: String,
+    matcher: Matcher,
+    from_page: PageNum,
+    cancelled: Arc<AtomicBool>,
+) {
+    std::thread::spawn(move || {
+        let pdf_doc = match PdfDocument::open(&filepath) {
+            Ok(pdf_doc) => pdf_doc,
+            Err(e) => {
+                println!("Error opening {} to search it: {}", filepath, e);
+                return;
+            }
+        };
+        let page_count = pdf_doc.page_count().unwrap_or(0) as usize;
+        if page_count == 0 {
+            return;
+        }
+
+        let search = |page_num: PageNum| match pdf_doc.load_page(page_num as i32) {
+            Ok(page) => page_hits(&page, &matcher),
+            Err(_) => Vec::new(),
+        };
+
+        let from_page = from_page.min(page_count - 1);
+        let (mut first, mut last) = (from_page, from_page);
+        let mut hits = vec![(from_page, search(from_page))];
+        let mut pages_in_batch = 1;
+        loop {
+            if cancelled.load(Ordering::SeqCst) {
+                return;
+            }
+            let finished = first == 0 && last == page_count - 1;
+            if finished || pages_in_batch >= PAGES_PER_BATCH {
+                let batch = SearchBatch {
+                    cancelled: cancelled.clone(),
+                    first,
+                    last,
+                    hits: std::mem::take(&mut hits),
+                };
+                if let Err(e) =
+                    sink.submit_command(SEARCH_BATCH, Box::new(batch), Target::Window(window_id))
+                {
+                    println!("error sending search results: {}", e);
+                    return;
+                }
+                if finished {
+                    return;
+                }
+                pages_in_batch = 0;
+            }
+
+            if first > 0 {
+                first -= 1;
+                hits.push((first, search(first)));
+                pages_in_batch += 1;
+            }
+            if last < page_count - 1 {
+                last += 1;
+                hits.push((last, search(last)));
+                pages_in_batch += 1;
+            }
+        }
+    });
+}
@@ -0,0 +1,441 @@
+use druid::widget::prelude::*;
+use druid::{Lens, LocalizedString, Selector, UnitPoint, WidgetExt, WindowDesc};
+
+use druid::im::{HashMap, Vector};
+use druid::widget::{Button, Flex, Label, LineBreaking, List, Scroll};
+
+use std::hash::Hash;
+use std::path::Path;
+
+use crate::fingerprint::FINGERPRINT_ALIASES_FILE;
+use crate::global_marks::GLOBAL_MARKS_FILE;
+use crate::review::ReviewState;
+use crate::{AppState, DocumentInfo, Fingerprint, PageNum};
+
+pub const RESOLVE_SYNC_CONFLICT: Selector<(SyncConflict, bool)> =
+    Selector::new("resolve-sync-conflict");
+
+// which entry of a book's info was changed differently here and on another machine
+#[derive(Clone, Debug, Data, PartialEq)]
+pub enum ConflictField {
+    Tags(PageNum),
+    Bookmark(String),
+    CustomMargins(PageNum),
+    ColorInversionRectangles(PageNum),
+    Highlights(PageNum),
+    Notes(PageNum),
+    TagName(usize),
+    Prerequisite(Fingerprint),
+}
+
+impl ConflictField {
+    fn describe(&self) -> String {
+        match self {
+            ConflictField::Tags(page) => format!("tags on page {}", page + 1),
+            ConflictField::Bookmark(name) => format!("bookmark \"{}\"", name),
+            ConflictField::CustomMargins(page) => format!("crop margins of page {}", page + 1),
+            ConflictField::ColorInversionRectangles(page) => {
+                format!("colour inversions on page {}", page + 1)
+            }
+            ConflictField::Highlights(page) => format!("highlights on page {}", page + 1),
+            ConflictField::Notes(page) => format!("notes on page {}", page + 1),
+            ConflictField::TagName(bit) => format!("name and colour of tag {}", bit),
+            ConflictField::Prerequisite(_) => "pages needing a prerequisite book".to_string(),
+        }
+    }
+
+    fn describe_value(&self, info: &DocumentInfo) -> String {
+        match self {
+            ConflictField::Tags(page) => match info.tags.get(page) {
+                Some(bits) if *bits != 0 => (0..16)
+                    .filter(|bit| bits & (1 << bit) != 0)
+                    .map(|bit| bit.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                _ => "none".to_string(),
+            },
+            ConflictField::Bookmark(name) => match info.bookmarks.get(name) {
+                Some(page) => format!("page {}", page + 1),
+                None => "removed".to_string(),
+            },
+            ConflictField::CustomMargins(page) => match info.custom_margins.get(page) {
+                Some(r) => format!("{:.2}, {:.2} - {:.2}, {:.2}", r.x0, r.y0, r.x1, r.y1),
+                None => "book's default margins".to_string(),
+            },
+            ConflictField::ColorInversionRectangles(page) => {
+                match info.color_inversion_rectangles.get(page) {
+                    Some(rects) => format!("{} rectangles", rects.len()),
+                    None => "none".to_string(),
+                }
+            }
+            ConflictField::Highlights(page) => match info.highlights.get(page) {
+                Some(highlights) => format!("{} highlights", highlights.len()),
+                None => "none".to_string(),
+            },
+            ConflictField::Notes(page) => match info.notes.get(page) {
+                Some(notes) => notes
+                    .iter()
+                    .map(|note| format!("\"{}\"", note.preview()))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                None => "none".to_string(),
+            },
+            ConflictField::TagName(bit) => match info.tag_table.get(bit) {
+                Some(tag) => format!("\"{}\" ({})", tag.name, tag.color),
+                None => "the default".to_string(),
+            },
+            ConflictField::Prerequisite(fingerprint) => match info.prerequisites.get(fingerprint) {
+                Some(prerequisite) => match prerequisite.pages {
+                    Some((first, last)) => format!("pages {} to {}", first + 1, last + 1),
+                    None => "the whole book".to_string(),
+                },
+                None => "not needed".to_string(),
+            },
+        }
+    }
+
+    // overwrite this entry of `info` with whatever `source` has for it
+    pub fn copy_value(&self, source: &DocumentInfo, info: &mut DocumentInfo) {
+        match self {
+            ConflictField::Tags(page) => copy_entry(&source.tags, &mut info.tags, page),
+            ConflictField::Bookmark(name) => {
+                copy_entry(&source.bookmarks, &mut info.bookmarks, name)
+            }
+            ConflictField::CustomMargins(page) => {
+                copy_entry(&source.custom_margins, &mut info.custom_margins, page)
+            }
+            ConflictField::ColorInversionRectangles(page) => copy_entry(
+                &source.color_inversion_rectangles,
+                &mut info.color_inversion_rectangles,
+                page,
+            ),
+            ConflictField::Highlights(page) => {
+                copy_entry(&source.highlights, &mut info.highlights, page)
+            }
+            ConflictField::Notes(page) => copy_entry(&source.notes, &mut info.notes, page),
+            ConflictField::TagName(bit) => copy_entry(&source.tag_table, &mut info.tag_table, bit),
+            ConflictField::Prerequisite(fingerprint) => {
+                copy_entry(&source.prerequisites, &mut info.prerequisites, fingerprint)
+            }
+        }
+    }
+}
+
+fn copy_entry<K, V>(source: &HashMap<K, V>, dest: &mut HashMap<K, V>, key: &K)
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    match source.get(key) {
+        Some(value) => {
+            dest.insert(key.clone(), value.clone());
+        }
+        None => {
+            dest.remove(key);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Data, Lens, PartialEq)]
+pub struct SyncConflict {
+    pub fingerprint: Fingerprint,
+    pub field: ConflictField,
+    pub summary: String,
+    // the version that arrived through the sync directory, so "keep theirs" can be applied later
+    pub theirs: DocumentInfo,
+}
+
+impl SyncConflict {
+    pub fn new(field: ConflictField, ours: &DocumentInfo, theirs: &DocumentInfo) -> Self {
+        SyncConflict {
+            fingerprint: ours.fingerprint.clone(),
+            summary: format!(
+                "{}\n{} -- on this machine: {}, synced copy: {}",
+                ours.description,
+                field.describe(),
+                field.describe_value(ours),
+                field.describe_value(theirs)
+            ),
+            field,
+            theirs: theirs.clone(),
+        }
+    }
+
+    pub fn is_about(&self, other: &SyncConflict) -> bool {
+        self.fingerprint == other.fingerprint && self.field == other.field
+    }
+}
+
+// a value only counts as changed on one side if it differs from the last version both sides agreed on
+fn merge_value<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> T {
+    if ours == base {
+        theirs.clone()
+    } else {
+        ours.clone()
+    }
+}
+
+// three-way merge of one map: keys changed on only one side take that side's value, keys changed on both sides to different values are reported and keep our value for now
+fn merge_map<K, V>(
+    base: &HashMap<K, V>,
+    ours: &HashMap<K, V>,
+    theirs: &HashMap<K, V>,
+    conflicting_keys: &mut Vec<K>,
+) -> HashMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + PartialEq,
+{
+    let mut merged = HashMap::<K, V>::new();
+
+    let keys: std::collections::HashSet<&K> =
+        base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+
+    for key in keys {
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        let chosen = if o == t {
+            o
+        } else if o == b {
+            t
+        } else if t == b {
+            o
+        } else {
+            conflicting_keys.push(key.clone());
+            o
+        };
+        if let Some(value) = chosen {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+    merged
+}
+
+// for running totals, like reading time: both machines' additions since `base` are kept, so they never conflict
+fn merge_counts<K>(
+    base: &HashMap<K, u64>,
+    ours: &HashMap<K, u64>,
+    theirs: &HashMap<K, u64>,
+) -> HashMap<K, u64>
+where
+    K: Hash + Eq + Clone,
+{
+    let keys: std::collections::HashSet<&K> =
+        base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+
+    keys.into_iter()
+        .map(|key| {
+            let count = |map: &HashMap<K, u64>| *map.get(key).unwrap_or(&0);
+            (
+                key.clone(),
+                (count(ours) + count(theirs)).saturating_sub(count(base)),
+            )
+        })
+        .collect()
+}
+
+// a page graded differently on two machines keeps whichever grading is more recent, there's nothing useful to ask the user
+fn merge_reviews(
+    base: &HashMap<PageNum, ReviewState>,
+    ours: &HashMap<PageNum, ReviewState>,
+    theirs: &HashMap<PageNum, ReviewState>,
+) -> HashMap<PageNum, ReviewState> {
+    let mut conflicting_pages = Vec::<PageNum>::new();
+    let mut merged = merge_map(base, ours, theirs, &mut conflicting_pages);
+    for page in conflicting_pages {
+        if let (Some(our_state), Some(their_state)) = (ours.get(&page), theirs.get(&page)) {
+            if their_state.last_reviewed > our_state.last_reviewed {
+                merged.insert(page, their_state.clone());
+            }
+        }
+    }
+    merged
+}
+
+// `base` is the last version of the file this machine wrote or read, `theirs` is what's in the sync directory now
+pub fn merge_document_info(
+    base: &DocumentInfo,
+    ours: &DocumentInfo,
+    theirs: &DocumentInfo,
+) -> (DocumentInfo, Vec<ConflictField>) {
+    let mut conflicts = Vec::<ConflictField>::new();
+
+    let mut tag_conflicts = Vec::<PageNum>::new();
+    let mut bookmark_conflicts = Vec::<String>::new();
+    let mut margin_conflicts = Vec::<PageNum>::new();
+    let mut inversion_conflicts = Vec::<PageNum>::new();
+    let mut highlight_conflicts = Vec::<PageNum>::new();
+    let mut note_conflicts = Vec::<PageNum>::new();
+    let mut tag_name_conflicts = Vec::<usize>::new();
+    let mut prerequisite_conflicts = Vec::<Fingerprint>::new();
+
+    let merged = DocumentInfo {
+        default_margins: merge_value(
+            &base.default_margins,
+            &ours.default_margins,
+            &theirs.default_margins,
+        ),
+        custom_margins: merge_map(
+            &base.custom_margins,
+            &ours.custom_margins,
+            &theirs.custom_margins,
+            &mut margin_conflicts,
+        ),
+        color_inversion_rectangles: merge_map(
+            &base.color_inversion_rectangles,
+            &ours.color_inversion_rectangles,
+            &theirs.color_inversion_rectangles,
+            &mut inversion_conflicts,
+        ),
+        fingerprint: ours.fingerprint.clone(),
+        contents_page: merge_value(&base.contents_page, &ours.contents_page, &theirs.contents_page),
+        bookmarks: merge_map(
+            &base.bookmarks,
+            &ours.bookmarks,
+            &theirs.bookmarks,
+            &mut bookmark_conflicts,
+        ),
+        tags: merge_map(&base.tags, &ours.tags, &theirs.tags, &mut tag_conflicts),
+        most_recent_page: merge_value(
+            &base.most_recent_page,
+            &ours.most_recent_page,
+            &theirs.most_recent_page,
+        ),
+        page_count: usize::max(ours.page_count, theirs.page_count),
+        page_offset: merge_value(&base.page_offset, &ours.page_offset, &theirs.page_offset),
+        prerequisites: merge_map(
+            &base.prerequisites,
+            &ours.prerequisites,
+            &theirs.prerequisites,
+            &mut prerequisite_conflicts,
+        ),
+        description: merge_value(&base.description, &ours.description, &theirs.description),
+        highlights: merge_map(
+            &base.highlights,
+            &ours.highlights,
+            &theirs.highlights,
+            &mut highlight_conflicts,
+        ),
+        notes: merge_map(&base.notes, &ours.notes, &theirs.notes, &mut note_conflicts),
+        pdf_annotations_imported: ours.pdf_annotations_imported || theirs.pdf_annotations_imported,
+        tag_table: merge_map(
+            &base.tag_table,
+            &ours.tag_table,
+            &theirs.tag_table,
+            &mut tag_name_conflicts,
+        ),
+        reading_time: merge_counts(&base.reading_time, &ours.reading_time, &theirs.reading_time),
+        daily_reading_time: merge_counts(
+            &base.daily_reading_time,
+            &ours.daily_reading_time,
+            &theirs.daily_reading_time,
+        ),
+        reviews: merge_reviews(&base.reviews, &ours.reviews, &theirs.reviews),
+        schema_version: ours.schema_version,
+    };
+
+    conflicts.extend(tag_conflicts.into_iter().map(ConflictField::Tags));
+    conflicts.extend(bookmark_conflicts.into_iter().map(ConflictField::Bookmark));
+    conflicts.extend(margin_conflicts.into_iter().map(ConflictField::CustomMargins));
+    conflicts.extend(
+        inversion_conflicts
+            .into_iter()
+            .map(ConflictField::ColorInversionRectangles),
+    );
+    conflicts.extend(highlight_conflicts.into_iter().map(ConflictField::Highlights));
+    conflicts.extend(note_conflicts.into_iter().map(ConflictField::Notes));
+    conflicts.extend(tag_name_conflicts.into_iter().map(ConflictField::TagName));
+    conflicts.extend(
+        prerequisite_conflicts
+            .into_iter()
+            .map(ConflictField::Prerequisite),
+    );
+
+    (merged, conflicts)
+}
+
+fn make_conflict_row() -> impl Widget<SyncConflict> {
+    Flex::row()
+        .with_flex_child(
+            Label::new(|conflict: &SyncConflict, _env: &_| conflict.summary.clone())
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .align_vertical(UnitPoint::LEFT)
+                .padding(10.0)
+                .expand_width(),
+            1.,
+        )
+        .with_child(Button::new("Keep this machine's").on_click(
+            |ctx, conflict: &mut SyncConflict, _env| {
+                ctx.submit_command(RESOLVE_SYNC_CONFLICT.with((conflict.clone(), false)))
+            },
+        ))
+        .with_default_spacer()
+        .with_child(Button::new("Take synced copy's").on_click(
+            |ctx, conflict: &mut SyncConflict, _env| {
+                ctx.submit_command(RESOLVE_SYNC_CONFLICT.with((conflict.clone(), true)))
+            },
+        ))
+        .with_default_spacer()
+}
+
+pub fn make_sync_conflicts_window() -> WindowDesc<AppState> {
+    let ui = Flex::column()
+        .with_child(
+            Label::new(
+                "These were changed differently on this machine and another one since they last synced:",
+            )
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding(10.0),
+        )
+        .with_flex_child(
+            Scroll::new(List::new(make_conflict_row))
+                .vertical()
+                .lens(AppState::sync_conflicts),
+            1.,
+        );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Resolve sync conflicts"))
+        .window_size((700., 400.))
+}
+
+pub fn conflicts_for(
+    fields: Vec<ConflictField>,
+    ours: &DocumentInfo,
+    theirs: &DocumentInfo,
+) -> Vector<SyncConflict> {
+    fields
+        .into_iter()
+        .map(|field| SyncConflict::new(field, ours, theirs))
+        .collect()
+}
+
+// sync clients that can't decide which of two versions of a file wins keep both, renaming one of them:
+// Dropbox: "<fingerprint> (conflicted copy 2026-10-01).json" or "<fingerprint> (Someone's conflicted copy 2026-10-01).json"
+// Syncthing: "<fingerprint>.sync-conflict-20261001-120000-ABCDEFG.json"
+// the name of the file a conflicted copy is a copy of
+pub fn conflicted_copy_of(path: &Path) -> Option<String> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+
+    if let Some(idx) = stem.find(".sync-conflict-") {
+        return Some(format!("{}.json", &stem[..idx]));
+    }
+
+    if stem.contains("conflicted copy") {
+        if let Some(idx) = stem.find(" (") {
+            return Some(format!("{}.json", &stem[..idx]));
+        }
+    }
+    None
+}
+
+// the global marks and fingerprint aliases are conflicted too sometimes, but they aren't books
+pub fn conflicted_copy_fingerprint(path: &Path) -> Option<Fingerprint> {
+    let original = conflicted_copy_of(path)?;
+    if original == GLOBAL_MARKS_FILE || original == FINGERPRINT_ALIASES_FILE {
+        return None;
+    }
+    original.strip_suffix(".json").map(|stem| stem.to_string())
+}
@@ -0,0 +1,177 @@
+use druid::im::Vector;
+
+use crate::tag_table::{TagDef, TAG_COUNT};
+
+// an expression over a page's tags, like `3 & !5`, `2 | 4` or `red & (blue | green)`
+// `!` binds tightest, then `&`, then `|`
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagQuery {
+    Tag(usize),
+    Not(Box<TagQuery>),
+    And(Box<TagQuery>, Box<TagQuery>),
+    Or(Box<TagQuery>, Box<TagQuery>),
+}
+
+impl TagQuery {
+    // Ok(None) for an empty query, tag names are looked up in `tags`, the book's tag table
+    pub fn parse(text: &str, tags: &Vector<TagDef>) -> Result<Option<TagQuery>, String> {
+        let tokens = tokenize(text, tags)?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let query = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Some(query)),
+            Some(token) => Err(format!("unexpected {}", token.describe())),
+        }
+    }
+
+    pub fn matches(&self, bits: u16) -> bool {
+        match self {
+            TagQuery::Tag(bit) => bits & (1 << bit) != 0,
+            TagQuery::Not(q) => !q.matches(bits),
+            TagQuery::And(a, b) => a.matches(bits) && b.matches(bits),
+            TagQuery::Or(a, b) => a.matches(bits) || b.matches(bits),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Tag(usize),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Tag(bit) => format!("tag {}", bit),
+            Token::And => "\"&\"".to_string(),
+            Token::Or => "\"|\"".to_string(),
+            Token::Not => "\"!\"".to_string(),
+            Token::Open => "\"(\"".to_string(),
+            Token::Close => "\")\"".to_string(),
+        }
+    }
+}
+
+fn tokenize(text: &str, tags: &Vector<TagDef>) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::<Token>::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            // "&&" and "||" are read the same as "&" and "|"
+            '&' => {
+                if chars.peek() == Some(&'&') {
+                    chars.next();
+                }
+                tokens.push(Token::And)
+            }
+            '|' => {
+                if chars.peek() == Some(&'|') {
+                    chars.next();
+                }
+                tokens.push(Token::Or)
+            }
+            '!' | '-' => tokens.push(Token::Not),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            c if c.is_ascii_digit() => {
+                let bit = c.to_digit(10).unwrap_or(0) as usize;
+                tokens.push(Token::Tag(bit))
+            }
+            c if c.is_alphabetic() => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let lower = word.to_lowercase();
+                tokens.push(match lower.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => match (0..TAG_COUNT).find(|bit| {
+                        tags.get(*bit)
+                            .map_or(false, |t| t.name.to_lowercase() == lower)
+                    }) {
+                        Some(bit) => Token::Tag(bit),
+                        None => return Err(format!("no tag is called \"{}\"", word)),
+                    },
+                })
+            }
+            c => return Err(format!("unexpected \"{}\"", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next_is(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<TagQuery, String> {
+        let mut query = self.and()?;
+        while self.next_is(&Token::Or) {
+            query = TagQuery::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<TagQuery, String> {
+        let mut query = self.unary()?;
+        while self.next_is(&Token::And) {
+            query = TagQuery::And(Box::new(query), Box::new(self.unary()?));
+        }
+        Ok(query)
+    }
+
+    fn unary(&mut self) -> Result<TagQuery, String> {
+        if self.next_is(&Token::Not) {
+            Ok(TagQuery::Not(Box::new(self.unary()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<TagQuery, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Tag(bit)) => Ok(TagQuery::Tag(bit)),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                if self.next_is(&Token::Close) {
+                    Ok(query)
+                } else {
+                    Err("missing \")\"".to_string())
+                }
+            }
+            Some(token) => Err(format!("unexpected {}", token.describe())),
+            None => Err("unfinished expression".to_string()),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use druid::im::Vector;
+use druid::widget::prelude::*;
+use druid::widget::{
+    Button, Flex, Label, LineBreaking, List, Painter, Scope, ScopeTransfer, TextBox,
+};
+use druid::{
+    commands as sys_cmds, Color, Lens, LensExt, LocalizedString, Selector, WidgetExt, WindowDesc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scrollbar_widget::COLORS;
+use crate::{AppState, DocumentInfo, Fingerprint};
+
+// which book's tag names to edit, or None for the defaults in the preferences
+pub const EDIT_TAG_TABLE: Selector<Option<Fingerprint>> = Selector::new("edit-tag-table");
+pub const SAVE_TAG_TABLE: Selector<(Option<Fingerprint>, Vector<TagRow>)> =
+    Selector::new("save-tag-table");
+
+// names for the built-in tag colours in scrollbar_widget::COLORS, by tag number
+pub const TAG_COLOR_NAMES: [&str; 10] = [
+    "black", "brown", "red", "orange", "yellow", "green", "blue", "purple", "grey", "white",
+];
+
+// tags are toggled with the digit keys 1 to 9, 0 clears them
+pub const FIRST_TAG: usize = 1;
+pub const TAG_COUNT: usize = 10;
+
+#[derive(Clone, Debug, Data, Serialize, Deserialize, PartialEq, Lens)]
+pub struct TagDef {
+    pub name: String,
+    // "#rrggbb", so it can be typed in and read in the saved json
+    pub color: String,
+}
+
+impl TagDef {
+    pub fn color(&self) -> Color {
+        Color::from_hex_str(&self.color).unwrap_or(Color::GRAY)
+    }
+}
+
+fn hex(color: &Color) -> String {
+    let (r, g, b, _) = color.as_rgba8();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn built_in_tag(bit: usize) -> TagDef {
+    TagDef {
+        name: TAG_COLOR_NAMES[bit % TAG_COUNT].to_string(),
+        color: hex(&COLORS[bit % TAG_COUNT]),
+    }
+}
+
+pub fn default_tag_table() -> Vector<TagDef> {
+    (0..TAG_COUNT).map(built_in_tag).collect()
+}
+
+fn default_tag(bit: usize, defaults: &Vector<TagDef>) -> TagDef {
+    defaults
+        .get(bit)
+        .cloned()
+        .unwrap_or_else(|| built_in_tag(bit))
+}
+
+// the book's own names and colours where it has them, the preferences' defaults for the rest
+pub fn tag_table(info: &DocumentInfo, defaults: &Vector<TagDef>) -> Vector<TagDef> {
+    (0..TAG_COUNT)
+        .map(|bit| match info.tag_table.get(&bit) {
+            Some(tag) => tag.clone(),
+            None => default_tag(bit, defaults),
+        })
+        .collect()
+}
+
+pub fn tag_names(bits: u16, table: &Vector<TagDef>) -> Vec<String> {
+    (0..TAG_COUNT)
+        .filter(|bit| bits & (1 << bit) != 0)
+        .map(|bit| format!("{} {}", bit, table[bit].name))
+        .collect()
+}
+
+// only tags that differ from the defaults are stored with the book, so renaming a default later still reaches it
+pub fn set_book_tags(info: &mut DocumentInfo, rows: &Vector<TagRow>, defaults: &Vector<TagDef>) {
+    for row in rows {
+        if row.tag == default_tag(row.bit, defaults) {
+            info.tag_table.remove(&row.bit);
+        } else {
+            info.tag_table.insert(row.bit, row.tag.clone());
+        }
+    }
+}
+
+pub fn set_default_tags(defaults: &mut Vector<TagDef>, rows: &Vector<TagRow>) {
+    let table: Vector<TagDef> = (0..TAG_COUNT)
+        .map(|bit| match rows.iter().find(|row| row.bit == bit) {
+            Some(row) => row.tag.clone(),
+            None => default_tag(bit, defaults),
+        })
+        .collect();
+    *defaults = table;
+}
+
+#[derive(Clone, Debug, Data, Lens, PartialEq)]
+pub struct TagRow {
+    pub bit: usize,
+    pub tag: TagDef,
+}
+
+fn rows_of(table: &Vector<TagDef>) -> Vector<TagRow> {
+    (FIRST_TAG..TAG_COUNT)
+        .map(|bit| TagRow {
+            bit,
+            tag: default_tag(bit, table),
+        })
+        .collect()
+}
+
+#[derive(Clone, Data, Lens)]
+struct TagTableEditor {
+    fingerprint: Option<Fingerprint>,
+    rows: Vector<TagRow>,
+    // what "Use defaults" goes back to
+    defaults: Vector<TagRow>,
+}
+
+// like the note editor, changes stay in the window until "Save" is clicked
+struct TagTableEditorTransfer;
+
+impl ScopeTransfer for TagTableEditorTransfer {
+    type In = AppState;
+    type State = TagTableEditor;
+
+    fn read_input(&self, _my_state: &mut Self::State, _external: &Self::In) {}
+
+    fn write_back_input(&self, _my_state: &Self::State, _external: &mut Self::In) {}
+}
+
+fn make_tag_row() -> impl Widget<TagRow> {
+    Flex::row()
+        .with_child(Label::new(|row: &TagRow, _env: &_| format!("{}", row.bit)).fix_width(20.))
+        .with_child(
+            Painter::new(|ctx, row: &TagRow, _env| {
+                let rect = ctx.size().to_rect();
+                ctx.fill(rect, &row.tag.color());
+            })
+            .fix_size(20., 20.),
+        )
+        .with_default_spacer()
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("Name")
+                .lens(TagRow::tag.then(TagDef::name))
+                .expand_width(),
+            1.,
+        )
+        .with_default_spacer()
+        .with_child(
+            TextBox::new()
+                .with_placeholder("#rrggbb")
+                .lens(TagRow::tag.then(TagDef::color))
+                .fix_width(90.),
+        )
+        .padding((10., 3.))
+}
+
+pub fn make_tag_table_window(
+    data: &AppState,
+    fingerprint: Option<Fingerprint>,
+) -> WindowDesc<AppState> {
+    let defaults = rows_of(&data.preferences.default_tag_table);
+    let (heading, rows) = match &fingerprint {
+        Some(fp) => match data.all_local_documents_info.get(fp) {
+            Some(info) => (
+                format!(
+                    "Tag names and colours for \"{}\", used in its overview panel and exports:",
+                    info.description
+                ),
+                rows_of(&tag_table(info, &data.preferences.default_tag_table)),
+            ),
+            None => (String::new(), defaults.clone()),
+        },
+        None => (
+            "Tag names and colours for books that haven't been given their own:".to_string(),
+            defaults.clone(),
+        ),
+    };
+    let is_book = fingerprint.is_some();
+
+    let mut buttons = Flex::row().with_child(Button::new("Save").on_click(
+        |ctx, data: &mut TagTableEditor, _env| {
+            ctx.submit_command(SAVE_TAG_TABLE.with((data.fingerprint.clone(), data.rows.clone())));
+            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+        },
+    ));
+    if is_book {
+        buttons.add_default_spacer();
+        buttons.add_child(Button::new("Use defaults").on_click(
+            |_ctx, data: &mut TagTableEditor, _env| {
+                data.rows = data.defaults.clone();
+            },
+        ));
+    }
+    buttons.add_default_spacer();
+    buttons.add_child(
+        Button::new("Cancel").on_click(|ctx, _data: &mut TagTableEditor, _env| {
+            ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+        }),
+    );
+
+    let ui = Scope::from_function(
+        move |_app_state: AppState| TagTableEditor {
+            fingerprint: fingerprint.clone(),
+            rows: rows.clone(),
+            defaults: defaults.clone(),
+        },
+        TagTableEditorTransfer,
+        Flex::column()
+            .with_child(
+                Label::new(heading)
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(10.0)
+                    .expand_width(),
+            )
+            .with_child(List::new(make_tag_row).lens(TagTableEditor::rows))
+            .with_child(buttons.padding(10.0)),
+    );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("Tag names"))
+        .window_size((450., 420.))
+}
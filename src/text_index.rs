@@ -0,0 +1,227 @@
+use druid::{ExtEventSink, Rect, Selector, Target};
+
+use mupdf::pdf::PdfDocument;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::highlights::{page_chars, PageChar};
+use crate::persistence::write_atomically;
+use crate::text_match::{Matcher, PageHit};
+use crate::{Fingerprint, PageNum};
+
+// from a view window that's just opened, asks for its book's text index to be loaded, or built if there isn't one yet
+pub const INDEX_DOCUMENT: Selector<Fingerprint> = Selector::new("index-document");
+// from the indexing thread
+pub const TEXT_INDEX_READY: Selector<(Fingerprint, Arc<TextIndex>)> =
+    Selector::new("text-index-ready");
+
+// bump when the stored format changes, older indexes are rebuilt rather than read
+const INDEX_VERSION: u32 = 1;
+
+// positions are stored as fractions of the page's width or height, scaled to fit a u16, which keeps the files small
+const SCALE: f64 = 10000.;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedLine {
+    y0: u16,
+    y1: u16,
+    text: String,
+    // left edge of each character, then the right edge of the last one
+    xs: Vec<u16>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedPage {
+    lines: Vec<IndexedLine>,
+}
+
+// every page's text with where each character is, so a search looks at the whole book at once rather than asking mupdf page by page
+// kept in "<local data directory>/text_index/<fingerprint>.json", it's worked out from the PDF so there's no need to sync it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TextIndex {
+    version: u32,
+    pages: Vec<IndexedPage>,
+}
+
+fn scaled(x: f64) -> u16 {
+    (x.max(0.).min(1.) * SCALE).round() as u16
+}
+
+fn unscaled(x: u16) -> f64 {
+    x as f64 / SCALE
+}
+
+impl IndexedPage {
+    fn from_chars(chars: &[PageChar]) -> Self {
+        let mut lines = Vec::<IndexedLine>::new();
+        let mut previous: Option<&PageChar> = None;
+        for c in chars {
+            if previous.map(|p| p.line) != Some(c.line) {
+                // the closing right edge of the line before
+                if let (Some(line), Some(p)) = (lines.last_mut(), previous) {
+                    line.xs.push(scaled(p.rect.x1));
+                }
+                lines.push(IndexedLine {
+                    y0: scaled(c.rect.y0),
+                    y1: scaled(c.rect.y1),
+                    text: String::new(),
+                    xs: Vec::new(),
+                });
+            }
+            if let Some(line) = lines.last_mut() {
+                line.y0 = line.y0.min(scaled(c.rect.y0));
+                line.y1 = line.y1.max(scaled(c.rect.y1));
+                line.text.push(c.ch);
+                line.xs.push(scaled(c.rect.x0));
+            }
+            previous = Some(c);
+        }
+        if let (Some(line), Some(p)) = (lines.last_mut(), previous) {
+            line.xs.push(scaled(p.rect.x1));
+        }
+        IndexedPage { lines }
+    }
+
+    fn span_rect(&self, (line, first, last): (usize, usize, usize)) -> Rect {
+        let line = &self.lines[line];
+        Rect::new(
+            unscaled(line.xs[first]),
+            unscaled(line.y0),
+            unscaled(line.xs[last + 1]),
+            unscaled(line.y1),
+        )
+    }
+
+    fn search(&self, matcher: &Matcher) -> Vec<PageHit> {
+        let lines: Vec<Vec<char>> = self
+            .lines
+            .iter()
+            .map(|line| line.text.chars().collect())
+            .collect();
+        matcher
+            .find(&lines)
+            .into_iter()
+            .map(|hit| PageHit {
+                rects: hit
+                    .spans
+                    .into_iter()
+                    .map(|span| self.span_rect(span))
+                    .collect(),
+                context: hit.context,
+            })
+            .collect()
+    }
+}
+
+impl TextIndex {
+    fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    // pages without hits are left out
+    pub fn search(&self, matcher: &Matcher) -> BTreeMap<PageNum, Vec<PageHit>> {
+        self.pages
+            .iter()
+            .enumerate()
+            .map(|(page, indexed)| (page, indexed.search(matcher)))
+            .filter(|(_, hits)| !hits.is_empty())
+            .collect()
+    }
+}
+
+fn index_path(index_dir: &Path, fingerprint: &str) -> PathBuf {
+    let mut path = index_dir.to_path_buf();
+    path.push(fingerprint);
+    path.set_extension("json");
+    path
+}
+
+fn load_index(path: &Path, page_count: usize) -> Option<TextIndex> {
+    let serialized = fs::read_to_string(path).ok()?;
+    let index: TextIndex = match serde_json::from_str(&serialized) {
+        Ok(index) => index,
+        Err(e) => {
+            println!("Error reading text index {:?}, rebuilding it: {}", path, e);
+            return None;
+        }
+    };
+    if index.version == INDEX_VERSION && index.page_count() == page_count {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+fn build_index(pdf_doc: &PdfDocument, page_count: usize) -> TextIndex {
+    let pages = (0..page_count)
+        .map(|page_num| match pdf_doc.load_page(page_num as i32) {
+            Ok(page) => IndexedPage::from_chars(&page_chars(&page)),
+            Err(e) => {
+                println!("Unable to load page {} for indexing: {}", page_num, e);
+                IndexedPage { lines: Vec::new() }
+            }
+        })
+        .collect();
+    TextIndex {
+        version: INDEX_VERSION,
+        pages,
+    }
+}
+
+fn save_index(index_dir: &Path, path: &Path, index: &TextIndex) {
+    if let Err(e) = fs::create_dir_all(index_dir) {
+        println!("Error creating text index directory: {}", e);
+        return;
+    }
+    match serde_json::to_string(index) {
+        Ok(serialized) => {
+            if let Err(e) = write_atomically(path, &serialized) {
+                println!("Error writing text index {:?}: {}", path, e);
+            }
+        }
+        Err(e) => println!("Error serializing text index: {}", e),
+    }
+}
+
+// extracting the text of a long book takes seconds, and even reading a stored index back isn't instant, so both happen away from the UI thread
+// `filepath` is opened again rather than sharing the open document, which can't be sent between threads
+pub fn load_or_build(
+    sink: ExtEventSink,
+    fingerprint: Fingerprint,
+    filepath: String,
+    index_dir: PathBuf,
+) {
+    std::thread::spawn(move || {
+        let pdf_doc = match PdfDocument::open(&filepath) {
+            Ok(pdf_doc) => pdf_doc,
+            Err(e) => {
+                println!("Error opening {} for indexing: {}", filepath, e);
+                return;
+            }
+        };
+        let page_count = pdf_doc.page_count().unwrap_or(0) as usize;
+
+        let path = index_path(&index_dir, &fingerprint);
+        let index = match load_index(&path, page_count) {
+            Some(index) => index,
+            None => {
+                let index = build_index(&pdf_doc, page_count);
+                save_index(&index_dir, &path, &index);
+                index
+            }
+        };
+
+        if let Err(e) = sink.submit_command(
+            TEXT_INDEX_READY,
+            Box::new((fingerprint, Arc::new(index))),
+            Target::Auto,
+        ) {
+            println!("error sending text index: {}", e);
+        }
+    });
+}
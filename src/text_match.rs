@@ -0,0 +1,351 @@
+use druid::{Data, Lens, Rect};
+
+use regex::{Regex, RegexBuilder};
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// a line ending in one of these followed by one starting with a letter may be a word split across the line break,
+// or a hyphenated word that happens to break there, so both readings are searched
+const HYPHENS: [char; 3] = ['-', '\u{00AD}', '\u{2010}'];
+
+#[derive(Clone, Copy, Debug, Default, Data, Lens, PartialEq)]
+pub struct FindOptions {
+    // the text typed is a regular expression rather than a phrase
+    pub regex: bool,
+    pub whole_word: bool,
+    pub case_sensitive: bool,
+    // otherwise accents are ignored, so "naive" finds "naïve" and the other way round
+    pub match_diacritics: bool,
+}
+
+// how far either side of a hit to look for the start and end of its sentence, in bytes of text
+const CONTEXT_LIMIT: usize = 80;
+
+pub struct Hit {
+    // the runs of characters the hit covers, one per line, each (line, first character, last character)
+    pub spans: Vec<(usize, usize, usize)>,
+    // the sentence it's in
+    pub context: String,
+}
+
+// a hit on a page, in page-normalised coordinates
+pub struct PageHit {
+    // one per line the hit covers
+    pub rects: Vec<Rect>,
+    pub context: String,
+}
+
+// searches the text as extracted line by line from the PDF, rather than with mupdf's plain substring search
+pub struct Matcher {
+    regex: Regex,
+    options: FindOptions,
+}
+
+// ligatures such as "ﬁ" are always spelt out, so they're found by typing "fi"
+fn normalize_char(ch: char, options: &FindOptions, out: &mut String) {
+    if options.match_diacritics {
+        out.extend(std::iter::once(ch).nfkc());
+    } else {
+        out.extend(
+            std::iter::once(ch)
+                .nfkd()
+                .filter(|c| !is_combining_mark(*c)),
+        );
+    }
+}
+
+fn normalize(text: &str, options: &FindOptions) -> String {
+    let mut out = String::new();
+    for ch in text.chars() {
+        normalize_char(ch, options, &mut out);
+    }
+    out
+}
+
+impl Matcher {
+    // the error, if the text isn't a valid regular expression, is short enough for the find bar
+    pub fn new(goal: &str, options: FindOptions) -> Result<Self, String> {
+        let pattern = if options.regex {
+            normalize(goal, &options)
+        } else {
+            // the page's runs of spaces and line breaks are each searched as a single space
+            let words: Vec<&str> = goal.split_whitespace().collect();
+            regex::escape(&normalize(&words.join(" "), &options))
+        };
+        let pattern = if options.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+
+        match RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+        {
+            Ok(regex) => Ok(Matcher { regex, options }),
+            Err(e) => Err(format!(
+                "not a regular expression, {}",
+                e.to_string()
+                    .lines()
+                    .last()
+                    .unwrap_or("")
+                    .trim_start_matches("error: ")
+            )),
+        }
+    }
+
+    // `lines` is a page's text, a line at a time in reading order
+    pub fn find(&self, lines: &[Vec<char>]) -> Vec<Hit> {
+        let (joined, hyphenated) = self.page_text(lines, false);
+        let mut hits = self.hits(&joined);
+        if hyphenated {
+            // hits found with the hyphens kept, such as "self-evident", that aren't already covered
+            let (kept, _) = self.page_text(lines, true);
+            for hit in self.hits(&kept) {
+                let overlaps = hits
+                    .iter()
+                    .any(|other| spans_overlap(&hit.spans, &other.spans));
+                if !overlaps {
+                    hits.push(hit);
+                }
+            }
+            hits.sort_by_key(|hit| (hit.spans[0].0, hit.spans[0].1));
+        }
+        hits
+    }
+
+    // the page's lines run together, with a hyphen at the end of a line dropped unless `keep_hyphens`
+    // also says whether there were any such hyphens
+    fn page_text(&self, lines: &[Vec<char>], keep_hyphens: bool) -> (PageText, bool) {
+        let mut page = PageText::default();
+        let mut any_hyphenated = false;
+
+        let mut joined_to_previous = false;
+        for (line_idx, line) in lines.iter().enumerate() {
+            let hyphenated = line.last().map_or(false, |ch| HYPHENS.contains(ch))
+                && lines
+                    .get(line_idx + 1)
+                    .and_then(|next| next.first())
+                    .map_or(false, |ch| ch.is_alphabetic());
+            any_hyphenated |= hyphenated;
+            if !joined_to_previous && page.needs_space() {
+                page.push(" ", " ", None);
+            }
+            for (idx, ch) in line.iter().enumerate() {
+                if hyphenated && !keep_hyphens && idx + 1 == line.len() {
+                    continue;
+                }
+                if ch.is_whitespace() {
+                    if page.needs_space() {
+                        page.push(" ", " ", Some((line_idx, idx)));
+                    }
+                    continue;
+                }
+                let mut normalized = String::new();
+                normalize_char(*ch, &self.options, &mut normalized);
+                page.push(&normalized, &ch.to_string(), Some((line_idx, idx)));
+            }
+            joined_to_previous = hyphenated;
+        }
+        (page, any_hyphenated)
+    }
+
+    fn hits(&self, page: &PageText) -> Vec<Hit> {
+        let mut hits = Vec::<Hit>::new();
+        for m in self.regex.find_iter(&page.text) {
+            let mut spans = Vec::<(usize, usize, usize)>::new();
+            for (line, idx) in page.sources[m.start()..m.end()].iter().flatten() {
+                match spans.last_mut() {
+                    Some((span_line, _, last)) if span_line == line => *last = *idx,
+                    _ => spans.push((*line, *idx, *idx)),
+                }
+            }
+            // patterns like "a*" also match nothing at all
+            if !spans.is_empty() {
+                hits.push(Hit {
+                    spans,
+                    context: page.context(m.start(), m.end()),
+                });
+            }
+        }
+        hits
+    }
+}
+
+// whether two hits share any character on the page
+fn spans_overlap(a: &[(usize, usize, usize)], b: &[(usize, usize, usize)]) -> bool {
+    a.iter().any(|(a_line, a_first, a_last)| {
+        b.iter().any(|(b_line, b_first, b_last)| {
+            a_line == b_line && a_first <= b_last && b_first <= a_last
+        })
+    })
+}
+
+// a page's text run together into one string to be searched, remembering where each part came from
+#[derive(Default)]
+struct PageText {
+    text: String,
+    // for each byte of `text`, the character on the page it came from
+    sources: Vec<Option<(usize, usize)>>,
+    // the text as it was before being normalised, for showing around hits
+    original: String,
+    // for each byte of `text`, where its character starts in `original`
+    original_offsets: Vec<usize>,
+}
+
+fn is_sentence_end(ch: char) -> bool {
+    ch == '.' || ch == '?' || ch == '!'
+}
+
+impl PageText {
+    fn needs_space(&self) -> bool {
+        !self.text.is_empty() && !self.text.ends_with(' ')
+    }
+
+    fn push(&mut self, normalized: &str, original: &str, source: Option<(usize, usize)>) {
+        self.text.push_str(normalized);
+        self.sources
+            .extend(std::iter::repeat(source).take(normalized.len()));
+        self.original_offsets
+            .extend(std::iter::repeat(self.original.len()).take(normalized.len()));
+        self.original.push_str(original);
+    }
+
+    fn original_offset(&self, offset: usize) -> usize {
+        self.original_offsets
+            .get(offset)
+            .copied()
+            .unwrap_or_else(|| self.original.len())
+    }
+
+    // back to the start of the sentence, or to a word boundary if that's a long way back
+    fn context_start(&self, start: usize) -> (usize, bool) {
+        let mut last_space = None;
+        for (i, ch) in self.text[..start].char_indices().rev() {
+            if is_sentence_end(ch) && self.text[i + 1..].starts_with(' ') {
+                return (i + 2, false);
+            }
+            if i + CONTEXT_LIMIT < start {
+                return (last_space.unwrap_or(i), true);
+            }
+            if ch == ' ' {
+                last_space = Some(i + 1);
+            }
+        }
+        (0, false)
+    }
+
+    fn context_end(&self, end: usize) -> (usize, bool) {
+        let mut last_space = None;
+        for (i, ch) in self.text[end..].char_indices() {
+            if is_sentence_end(ch) {
+                return (end + i + 1, false);
+            }
+            if i > CONTEXT_LIMIT {
+                return (last_space.unwrap_or(end + i), true);
+            }
+            if ch == ' ' {
+                last_space = Some(end + i);
+            }
+        }
+        (self.text.len(), false)
+    }
+
+    // the sentence a hit is in, as it's written on the page
+    fn context(&self, start: usize, end: usize) -> String {
+        let (context_start, cut_before) = self.context_start(start);
+        let (context_end, cut_after) = self.context_end(end);
+        let sentence = self.original
+            [self.original_offset(context_start)..self.original_offset(context_end)]
+            .trim();
+        format!(
+            "{}{}{}",
+            if cut_before { "…" } else { "" },
+            sentence,
+            if cut_after { "…" } else { "" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<Vec<char>> {
+        text.iter().map(|line| line.chars().collect()).collect()
+    }
+
+    fn spans(goal: &str, options: FindOptions, text: &[&str]) -> Vec<Vec<(usize, usize, usize)>> {
+        Matcher::new(goal, options)
+            .unwrap()
+            .find(&lines(text))
+            .into_iter()
+            .map(|hit| hit.spans)
+            .collect()
+    }
+
+    #[test]
+    fn ligatures_are_spelt_out() {
+        let text = ["\u{FB01}nd the \u{FB01}le"];
+        assert_eq!(
+            spans("file", FindOptions::default(), &text),
+            vec![vec![(0, 8, 10)]]
+        );
+    }
+
+    #[test]
+    fn accents_are_ignored_unless_asked_for() {
+        let text = ["a na\u{EF}ve view"];
+        assert_eq!(
+            spans("naive", FindOptions::default(), &text),
+            vec![vec![(0, 2, 6)]]
+        );
+        assert_eq!(
+            spans("na\u{EF}ve", FindOptions::default(), &["a naive view"]),
+            vec![vec![(0, 2, 6)]]
+        );
+
+        let match_diacritics = FindOptions {
+            match_diacritics: true,
+            ..FindOptions::default()
+        };
+        assert!(spans("naive", match_diacritics, &text).is_empty());
+        assert_eq!(
+            spans("na\u{EF}ve", match_diacritics, &text),
+            vec![vec![(0, 2, 6)]]
+        );
+    }
+
+    #[test]
+    fn words_split_by_a_hyphen_are_joined() {
+        let text = ["an exam-", "ple here"];
+        assert_eq!(
+            spans("example", FindOptions::default(), &text),
+            vec![vec![(0, 3, 6), (1, 0, 2)]]
+        );
+    }
+
+    #[test]
+    fn hyphenated_words_keep_their_hyphen() {
+        let text = ["a self-", "evident truth"];
+        assert_eq!(
+            spans("self-evident", FindOptions::default(), &text),
+            vec![vec![(0, 2, 6), (1, 0, 6)]]
+        );
+        // found once, not once with the hyphen and once without
+        assert_eq!(
+            spans("self", FindOptions::default(), &text),
+            vec![vec![(0, 2, 5)]]
+        );
+    }
+
+    #[test]
+    fn hyphens_before_a_number_are_left_alone() {
+        let text = ["pages 10-", "20"];
+        assert_eq!(
+            spans("10- 20", FindOptions::default(), &text),
+            vec![vec![(0, 6, 8), (1, 0, 1)]]
+        );
+    }
+}
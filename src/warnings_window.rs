@@ -0,0 +1,34 @@
+use druid::widget::prelude::*;
+use druid::{commands as sys_cmds, LocalizedString, WidgetExt, WindowDesc};
+
+use druid::widget::{Button, Flex, Label, LineBreaking, List, Scroll};
+
+use crate::AppState;
+
+// problems the user should know about but which don't stop anything working, e.g. a book info file that had to be set aside
+pub fn make_warnings_window() -> WindowDesc<AppState> {
+    let ui = Flex::column()
+        .with_flex_child(
+            Scroll::new(List::new(|| {
+                Label::new(|warning: &String, _env: &_| warning.clone())
+                    .with_line_break_mode(LineBreaking::WordWrap)
+                    .padding(10.0)
+                    .expand_width()
+            }))
+            .vertical()
+            .lens(AppState::warnings),
+            1.,
+        )
+        .with_child(
+            Button::new("OK")
+                .on_click(|ctx, data: &mut AppState, _env| {
+                    data.warnings.clear();
+                    ctx.submit_command(sys_cmds::CLOSE_WINDOW);
+                })
+                .padding(10.0),
+        );
+
+    WindowDesc::new(ui)
+        .title(LocalizedString::new("PDF Student warnings"))
+        .window_size((600., 300.))
+}